@@ -0,0 +1,34 @@
+//#![deny(rust_2018_idioms, unused, unused_import_braces, unused_lifetimes, unused_qualifications, warnings)]
+
+use {
+    std::{
+        io,
+        net::{
+            SocketAddr,
+            TcpListener,
+        },
+    },
+    structopt::StructOpt,
+    victory::{
+        frontend::run_game,
+        net::Server,
+        rules::Rules,
+        state::State,
+    },
+};
+
+#[derive(StructOpt)]
+struct Args {
+    /// The address to accept connections on
+    #[structopt(long = "addr", default_value = "0.0.0.0:18824")]
+    addr: SocketAddr,
+    /// Seed for all random decisions, to make the game reproducible
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+}
+
+#[paw::main]
+fn main(args: Args) -> io::Result<()> {
+    let mut state = if let Some(seed) = args.seed { State::with_seed(Rules::default(), seed) } else { State::<String>::default() };
+    run_game(&mut state, &mut Server::new(TcpListener::bind(args.addr)?))
+}
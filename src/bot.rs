@@ -0,0 +1,482 @@
+//! Built-in rule-based players. Bots decide based on their player's [`View`] only, so they can't cheat.
+
+use {
+    std::collections::{
+        HashMap,
+        HashSet,
+    },
+    itertools::Itertools as _,
+    rand::prelude::*,
+    rand_pcg::Pcg64,
+    smart_default::SmartDefault,
+    crate::{
+        frontend::{
+            Frontend,
+            lobby_input,
+        },
+        identities::{
+            Identity,
+            SoloIdentity,
+        },
+        roles::Role,
+        state::{
+            Action,
+            GameError,
+            IngameInput,
+            Input,
+            InputRequest,
+            Insight,
+            PlayerId,
+            Seat,
+            State,
+        },
+        view::View,
+    },
+};
+
+/// How cleverly a bot plays.
+#[derive(Debug, SmartDefault, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Difficulty {
+    /// Picks randomly among the available choices.
+    Easy,
+    /// Protects itself and its known partners, and goes after the players its identity wants dead.
+    #[default]
+    Normal,
+}
+
+/// A rule-based player.
+#[derive(Debug)]
+pub struct Bot<P: PlayerId> {
+    player: P,
+    difficulty: Difficulty,
+    rng: Pcg64,
+    /// the partners named in win attempts during the current segment, so a failed claim isn't repeated
+    claims: HashSet<Vec<Seat>>,
+    /// whether the current segment has gone on for so long that the bot plays like an easy bot, see [`Bots`]
+    impatient: bool,
+}
+
+impl<P: PlayerId> Bot<P> {
+    pub fn new(player: P, difficulty: Difficulty, seed: u64) -> Bot<P> {
+        Bot {
+            player, difficulty,
+            rng: Pcg64::seed_from_u64(seed),
+            claims: HashSet::default(),
+            impatient: false,
+        }
+    }
+
+    pub fn player(&self) -> &P { &self.player }
+
+    pub fn difficulty(&self) -> Difficulty { self.difficulty }
+
+    /// The difficulty the bot's decisions are currently made at.
+    fn playing_at(&self) -> Difficulty {
+        if self.impatient { Difficulty::Easy } else { self.difficulty }
+    }
+
+    /// Decides on an input for an in-game request the bot's player is awaited for. Returns `None` for meta requests, since joining and starting the game is up to the frontend.
+    ///
+    /// At any difficulty, the bot claims a win as soon as it knows enough partners.
+    pub fn decide(&mut self, view: &View<P>, ir: &InputRequest<P>) -> Option<Input<P>> {
+        if let Some(attempt) = self.win_attempt(view) { return Some(attempt) }
+        Some(match *ir {
+            InputRequest::Meta => return None,
+            InputRequest::Action(phase, _) => Input::Ingame(IngameInput::Action(self.action(view, phase)?)),
+            InputRequest::Angel(_, ref action, _) => Input::Ingame(self.angel(view, action)),
+        })
+    }
+
+    /// Like [`decide`](Bot::decide), but only makes choices which are always valid, e.g. passing or using no potions. Used after an input was rejected.
+    ///
+    /// All wolves vote for the same player here, so this is also used when the wolves have to vote again after a tie.
+    pub fn fallback(&mut self, view: &View<P>, ir: &InputRequest<P>) -> Option<Input<P>> {
+        let living = living_seats(view).collect_vec();
+        Some(match *ir {
+            InputRequest::Meta => return None,
+            InputRequest::Action(phase, _) => Input::Ingame(IngameInput::Action(match phase {
+                Role::Hunter => {
+                    let hunter = *acting_seats(view, phase).first()?;
+                    Action::Aim(hunter, *living.iter().find(|&&seat| seat != hunter).unwrap_or(&hunter))
+                }
+                Role::Vampire => Action::Bite(*living.first()?),
+                Role::Jester => Action::Swap(*living.first()?, *living.last()?),
+                Role::Seer => Action::Inspect(*living.first()?),
+                Role::Wolf => Action::Eat(acting_seats(view, phase).into_iter().map(|wolf| (wolf, *living.first().expect("a wolf is alive"))).collect()),
+                Role::Witch => Action::Potions { heal: false, poison: None },
+                Role::Mayor => Action::VoteResult(None),
+                Role::Angel => return None,
+            })),
+            InputRequest::Angel(..) => Input::Ingame(IngameInput::AngelPass),
+        })
+    }
+
+    /// Claims a win if the bot knows enough living partners from its insights and hasn't named them before.
+    fn win_attempt(&mut self, view: &View<P>) -> Option<Input<P>> {
+        let claimant = view.seat?;
+        let identity = view.character.as_ref()?.identity;
+        let needed = match identity {
+            Identity::Romeo | Identity::Juliet | Identity::Single => 1,
+            Identity::Churchill | Identity::Doyle | Identity::Mozart => 2,
+            Identity::Solo(_) => return None,
+        };
+        let partners = self.allies(view).into_iter().take(needed).collect_vec();
+        if partners.len() < needed || !self.claims.insert(partners.clone()) { return None }
+        Some(Input::Ingame(IngameInput::WinAttempt { claimant, identity, partners }))
+    }
+
+    fn action(&mut self, view: &View<P>, phase: Role) -> Option<Action> {
+        let own_seat = view.seat?;
+        let others = living_seats(view).filter(|&seat| seat != own_seat).collect_vec();
+        Some(match phase {
+            Role::Hunter => {
+                let hunter = *acting_seats(view, phase).first()?;
+                let target = if hunter == own_seat { view.character.as_ref().and_then(|c| c.target) } else { None };
+                if let Some(target) = target {
+                    let shoot = match self.playing_at() {
+                        Difficulty::Easy => self.rng.gen(),
+                        Difficulty::Normal => self.wants_deaths(view) && !self.allies(view).contains(&target),
+                    };
+                    if shoot { return Some(Action::Shoot(hunter)) }
+                }
+                let target = self.victim(view).or_else(|| others.choose(&mut self.rng).copied()).unwrap_or(own_seat);
+                Action::Aim(hunter, target)
+            }
+            Role::Vampire => {
+                let candidates = match self.playing_at() {
+                    Difficulty::Easy => others.clone(),
+                    Difficulty::Normal => {
+                        let allies = self.allies(view);
+                        others.iter().copied().filter(|seat| !allies.contains(seat) && view.bitten.iter().all(|(bitten, _)| bitten != seat)).collect()
+                    }
+                };
+                Action::Bite(*candidates.choose(&mut self.rng).or_else(|| others.first()).unwrap_or(&own_seat))
+            }
+            Role::Jester => {
+                let candidates = match self.playing_at() {
+                    Difficulty::Easy => living_seats(view).collect_vec(),
+                    Difficulty::Normal => others.clone(),
+                };
+                match *candidates.choose_multiple(&mut self.rng, 2).collect_vec() {
+                    [&seat0, &seat1] => Action::Swap(seat0, seat1),
+                    _ => Action::Swap(own_seat, own_seat),
+                }
+            }
+            Role::Seer => {
+                let uninspected = others.iter().copied().filter(|&seat| view.insights.iter().all(|insight| match insight {
+                    Insight::Identity(player, _) | Insight::Role(player, _) => *player != view.players[seat].0,
+                })).collect_vec();
+                let candidates = if uninspected.is_empty() || self.playing_at() == Difficulty::Easy { &others } else { &uninspected };
+                Action::Inspect(*candidates.choose(&mut self.rng).unwrap_or(&own_seat))
+            }
+            Role::Wolf => {
+                let target = match self.playing_at() {
+                    Difficulty::Easy => others.choose(&mut self.rng).copied(),
+                    // all wolves see the same wolves, so they agree on the first player to the left of the pack who isn't an ally
+                    Difficulty::Normal => self.victim(view).or_else(|| {
+                        let allies = self.allies(view);
+                        let first_wolf = view.wolves.iter().copied().min().unwrap_or(own_seat);
+                        (1..view.players.len())
+                            .map(|offset| (first_wolf + offset) % view.players.len())
+                            .find(|&seat| view.players[seat].1 && !view.wolves.contains(&seat) && !allies.contains(&seat))
+                    }),
+                }.or_else(|| others.first().copied()).unwrap_or(own_seat);
+                Action::Eat(acting_seats(view, phase).into_iter().map(|wolf| (wolf, target)).collect())
+            }
+            Role::Witch => {
+                let (health_potion, poison_potion) = view.character.as_ref().filter(|c| c.role == Role::Witch && !c.bitten).and_then(|c| c.potions).unwrap_or((false, false));
+                let heal = health_potion && view.dying.map_or(false, |dying| match self.playing_at() {
+                    Difficulty::Easy => self.rng.gen(),
+                    Difficulty::Normal => dying == own_seat || !self.wants_deaths(view) || self.allies(view).contains(&dying),
+                });
+                let poison = if poison_potion && self.playing_at() == Difficulty::Normal { self.victim(view) } else { None };
+                Action::Potions { heal, poison }
+            }
+            Role::Mayor => Action::VoteResult(match self.playing_at() {
+                Difficulty::Easy => if self.rng.gen() { others.choose(&mut self.rng).copied() } else { None },
+                Difficulty::Normal => self.victim(view).or_else(|| if self.wants_deaths(view) && self.rng.gen() {
+                    let allies = self.allies(view);
+                    others.iter().copied().filter(|seat| !allies.contains(seat)).collect_vec().choose(&mut self.rng).copied()
+                } else {
+                    None
+                }),
+            }),
+            Role::Angel => return None,
+        })
+    }
+
+    fn angel(&mut self, view: &View<P>, action: &Action) -> IngameInput {
+        let vetoable = view.pending_actors.iter().copied().filter(|&seat| view.shielded != Some(seat) && Some(seat) != view.seat).collect_vec();
+        let veto = match self.playing_at() {
+            Difficulty::Easy => self.rng.gen_bool(0.25),
+            Difficulty::Normal => {
+                let mut protected = self.allies(view);
+                protected.extend(view.seat);
+                match *action {
+                    Action::VoteResult(Some(seat)) | Action::Bite(seat) => protected.contains(&seat),
+                    Action::Swap(seat0, seat1) => protected.contains(&seat0) || protected.contains(&seat1),
+                    Action::Eat(ref votes) => votes.values().any(|target| protected.contains(target)),
+                    Action::Potions { poison, .. } => poison.map_or(false, |seat| protected.contains(&seat)),
+                    // the angel can't see whom a hunter shoots, and an inspection doesn't hurt
+                    Action::VoteResult(None) | Action::Shoot(_) | Action::Aim(_, _) | Action::Inspect(_) => false,
+                }
+            }
+        };
+        match vetoable.choose(&mut self.rng) {
+            Some(&seat) if veto => IngameInput::AngelVeto(seat),
+            _ => IngameInput::AngelPass,
+        }
+    }
+
+    /// The living players the bot knows to be in its party, according to its insights.
+    fn allies(&self, view: &View<P>) -> Vec<Seat> {
+        let party = if let Some(ref c) = view.character { c.identity.party() } else { return Vec::default() };
+        view.insights.iter()
+            .filter_map(|insight| if let Insight::Identity(player, identity) = insight { Some((player, identity)) } else { None })
+            .filter(|(_, identity)| identity.party() == party)
+            .filter_map(|(player, _)| view.players.iter().position(|(iter_player, alive)| iter_player == player && *alive))
+            .filter(|&seat| Some(seat) != view.seat)
+            .unique()
+            .collect()
+    }
+
+    /// Whether the bot's identity profits from players dying. The Queen and Churchill win when nobody dies.
+    fn wants_deaths(&self, view: &View<P>) -> bool {
+        !matches!(view.character.as_ref().map(|c| c.identity), Some(Identity::Solo(SoloIdentity::Queen)) | Some(Identity::Churchill))
+    }
+
+    /// A living player the bot knows its identity wants dead, if any.
+    fn victim(&mut self, view: &View<P>) -> Option<Seat> {
+        let identity = view.character.as_ref()?.identity;
+        view.insights.iter()
+            .filter(|insight| match (identity, insight) {
+                (Identity::Solo(SoloIdentity::Sherlock), Insight::Identity(_, Identity::Solo(SoloIdentity::JackTheRipper)))
+                | (Identity::Doyle, Insight::Identity(_, Identity::Solo(SoloIdentity::JackTheRipper)))
+                | (Identity::Solo(SoloIdentity::V), Insight::Identity(_, Identity::Solo(SoloIdentity::Queen)))
+                | (Identity::Solo(SoloIdentity::Macbeth), Insight::Role(_, Role::Mayor)) => true,
+                (_, _) => false,
+            })
+            .filter_map(|insight| match insight { Insight::Identity(player, _) | Insight::Role(player, _) => view.players.iter().position(|(iter_player, alive)| iter_player == player && *alive) })
+            .filter(|&seat| Some(seat) != view.seat)
+            .collect_vec()
+            .choose(&mut self.rng)
+            .copied()
+    }
+}
+
+/// The living seats, in seat order.
+fn living_seats<P: PlayerId>(view: &View<P>) -> impl Iterator<Item = Seat> + '_ {
+    view.players.iter().enumerate().filter(|(_, (_, alive))| *alive).map(|(seat, _)| seat)
+}
+
+/// The seats of the living players with the given role the viewer takes actions for.
+fn acting_seats<P: PlayerId>(view: &View<P>, role: Role) -> Vec<Seat> {
+    let own = view.seat.filter(|_| view.character.as_ref().map_or(false, |c| c.role == role && !c.bitten));
+    own.into_iter().chain(view.bitten.iter().filter(|&&(_, bitten_role)| bitten_role == role).map(|&(seat, _)| seat)).collect()
+}
+
+/// How many inputs a segment may take before the bots give up. Bots can get stuck, e.g. when everyone is a lover and nobody knows it.
+pub const MAX_SEGMENT_INPUTS: usize = 1000;
+
+/// A frontend for games played entirely by built-in bots. It joins the bots in the given order and starts the given number of segments.
+///
+/// Once a segment has taken half of [`MAX_SEGMENT_INPUTS`] inputs, the bots get impatient and play like easy bots, since random choices often break a stalemate, e.g. when an angel keeps vetoing the same attack. If a segment takes more than [`MAX_SEGMENT_INPUTS`] inputs, the game is stopped in the middle of that segment.
+#[derive(Debug)]
+pub struct Bots<P: PlayerId> {
+    bots: Vec<Bot<P>>,
+    /// how many segments are left to start
+    segments: usize,
+    /// whether the last input was rejected, so the bot should fall back to a safe choice
+    retry: bool,
+    /// whether the last input was already a fallback
+    fell_back: bool,
+    /// whether the last input was the wolves' votes, so another wolf request means they're voting again after a tie
+    ate: bool,
+    /// how many inputs the current segment has taken so far
+    inputs: usize,
+}
+
+impl<P: PlayerId> Bots<P> {
+    pub fn new(bots: Vec<Bot<P>>, segments: usize) -> Bots<P> {
+        Bots { bots, segments, retry: false, fell_back: false, ate: false, inputs: 0 }
+    }
+
+    /// Creates a bot with the given difficulty for each player, with seeds derived from the given one.
+    pub fn with_difficulty(players: impl IntoIterator<Item = P>, difficulty: Difficulty, seed: u64, segments: usize) -> Bots<P> {
+        let mut rng = Pcg64::seed_from_u64(seed);
+        Bots::new(players.into_iter().map(|player| Bot::new(player, difficulty, rng.gen())).collect(), segments)
+    }
+
+    pub fn bots(&self) -> &[Bot<P>] { &self.bots }
+}
+
+impl<P: PlayerId> Frontend<P> for Bots<P> {
+    type Error = GameError;
+
+    /// Returns `None` once the last segment is over, or if the current segment takes too long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a player who isn't one of the bots is asked for input.
+    fn give_input(&mut self, state: &State<P>, ir: InputRequest<P>) -> Result<Option<Input<P>>, GameError> {
+        let revote = self.ate && matches!(ir, InputRequest::Action(Role::Wolf, _));
+        self.fell_back = self.retry;
+        self.retry = false;
+        self.ate = false;
+        if let InputRequest::Meta = ir {
+            self.inputs = 0;
+            // claims only count for the segment they were made in
+            for bot in &mut self.bots { bot.claims.clear() }
+        } else {
+            self.inputs += 1;
+            if self.inputs > MAX_SEGMENT_INPUTS { return Ok(None) }
+            let impatient = self.inputs > MAX_SEGMENT_INPUTS / 2;
+            for bot in &mut self.bots { bot.impatient = impatient }
+        }
+        Ok(Some(match ir {
+            InputRequest::Meta => if let Some(input) = lobby_input(state, self.bots.iter().map(|bot| &bot.player), &mut self.segments) {
+                Input::Meta(input)
+            } else {
+                return Ok(None)
+            },
+            InputRequest::Action(_, ref awaited) | InputRequest::Angel(_, _, ref awaited) => {
+                // in the wolf phase, each awaited bot votes for the wolves it acts for
+                let mut votes = HashMap::default();
+                for player in awaited {
+                    let bot = self.bots.iter_mut().find(|bot| bot.player == *player).expect("a player who isn't a bot was asked for input");
+                    let view = state.view_for(player);
+                    let input = if self.fell_back || revote { bot.fallback(&view, &ir) } else { bot.decide(&view, &ir) };
+                    match input {
+                        Some(Input::Ingame(IngameInput::Action(Action::Eat(bot_votes)))) => votes.extend(bot_votes),
+                        Some(input) => return Ok(Some(input)),
+                        None => {}
+                    }
+                }
+                self.ate = true;
+                Input::Ingame(IngameInput::Action(Action::Eat(votes)))
+            }
+        }))
+    }
+
+    /// Stops the game with the error if a fallback input was rejected too.
+    fn reject(&mut self, error: GameError) -> Result<(), GameError> {
+        if self.fell_back { return Err(error) }
+        self.retry = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            frontend::run_game,
+            rules::Rules,
+            view::OwnCharacter,
+        },
+        super::*,
+    };
+
+    /// Bots only make valid inputs, or fall back to valid ones, for any number of players.
+    #[test]
+    fn bot_games() {
+        for difficulty in [Difficulty::Easy, Difficulty::Normal] {
+            let (mut games, mut finished) = (0, 0);
+            for num_players in 3..=8 {
+                for seed in 0..10 {
+                    games += 1;
+                    let mut state = State::with_seed(Rules::default(), seed);
+                    let mut bots = Bots::with_difficulty(0..num_players, difficulty, seed, 3);
+                    run_game(&mut state, &mut bots).unwrap();
+                    assert_eq!(state.players().len(), num_players);
+                    if let InputRequest::Meta = state.next_input() { finished += 1 }
+                }
+            }
+            // some games get stuck (see `MAX_SEGMENT_INPUTS`), but most of them should end
+            assert!(finished * 2 > games, "only {} of {} games at {:?} difficulty ended", finished, games, difficulty);
+        }
+    }
+
+    /// A view for a player in the given seat at a table of `num_players` living players.
+    fn view(seat: Seat, num_players: usize, role: Role, identity: Identity) -> View<usize> {
+        View {
+            seat: Some(seat),
+            character: Some(OwnCharacter { role, identity, bitten: false, potions: None, target: None }),
+            players: (0..num_players).map(|player| (player, true)).collect(),
+            phase: Some(role),
+            points: HashMap::default(),
+            insights: Vec::default(),
+            bitten: Vec::default(),
+            wolves: Vec::default(),
+            dying: None,
+            pending_action: None,
+            pending_actors: Vec::default(),
+            shielded: None,
+        }
+    }
+
+    // the bots in these tests are Masons who know only one partner, so they don't claim a win instead of acting
+
+    #[test]
+    fn witch_heals_ally() {
+        let mut view = view(0, 4, Role::Witch, Identity::Mozart);
+        view.character.as_mut().unwrap().potions = Some((true, false));
+        view.insights.push(Insight::Identity(2, Identity::Doyle));
+        let ir = InputRequest::Action(Role::Witch, vec![0]);
+        let mut bot = Bot::new(0, Difficulty::Normal, 0);
+        view.dying = Some(2);
+        assert!(matches!(bot.decide(&view, &ir), Some(Input::Ingame(IngameInput::Action(Action::Potions { heal: true, poison: None })))));
+        // Mozart profits from deaths, so strangers aren't saved
+        view.dying = Some(3);
+        assert!(matches!(bot.decide(&view, &ir), Some(Input::Ingame(IngameInput::Action(Action::Potions { heal: false, poison: None })))));
+    }
+
+    #[test]
+    fn wolves_agree() {
+        let ir = InputRequest::Action(Role::Wolf, vec![1, 3]);
+        let targets = [(1, Identity::Romeo), (3, Identity::Mozart)].iter().map(|&(seat, identity)| {
+            let mut view = view(seat, 6, Role::Wolf, identity);
+            view.wolves = vec![1, 3];
+            match Bot::new(seat, Difficulty::Normal, seat as u64).decide(&view, &ir) {
+                Some(Input::Ingame(IngameInput::Action(Action::Eat(votes)))) => {
+                    assert_eq!(votes.keys().copied().collect_vec(), vec![seat]);
+                    votes[&seat]
+                }
+                input => panic!("unexpected input: {:?}", input),
+            }
+        }).collect_vec();
+        assert_eq!(targets[0], targets[1]);
+        assert!(![1, 3].contains(&targets[0]));
+    }
+
+    #[test]
+    fn angel_vetoes_attack_on_ally() {
+        let mut view = view(0, 4, Role::Angel, Identity::Mozart);
+        view.phase = Some(Role::Wolf);
+        view.insights.push(Insight::Identity(2, Identity::Doyle));
+        view.pending_actors = vec![1];
+        let mut bot = Bot::new(0, Difficulty::Normal, 0);
+        let mut decide = |target: Seat| {
+            let action = Action::Eat(vec![(1, target)].into_iter().collect());
+            view.pending_action = Some(action.clone());
+            bot.decide(&view, &InputRequest::Angel(Role::Wolf, action, vec![0]))
+        };
+        assert!(matches!(decide(2), Some(Input::Ingame(IngameInput::AngelVeto(1)))));
+        assert!(matches!(decide(3), Some(Input::Ingame(IngameInput::AngelPass))));
+    }
+
+    #[test]
+    fn claims_forgotten_between_segments() {
+        let mut view = view(0, 3, Role::Mayor, Identity::Romeo);
+        view.insights.push(Insight::Identity(1, Identity::Juliet));
+        let ir = InputRequest::Action(Role::Mayor, vec![0]);
+        let mut bot = Bot::new(0, Difficulty::Normal, 0);
+        assert!(matches!(bot.decide(&view, &ir), Some(Input::Ingame(IngameInput::WinAttempt { .. }))));
+        // a failed claim isn't repeated during the same segment
+        assert!(matches!(bot.decide(&view, &ir), Some(Input::Ingame(IngameInput::Action(_)))));
+        let mut bots = Bots::new(vec![bot], 1);
+        bots.give_input(&State::default(), InputRequest::Meta).unwrap();
+        assert!(matches!(bots.bots[0].decide(&view, &ir), Some(Input::Ingame(IngameInput::WinAttempt { .. }))));
+    }
+}
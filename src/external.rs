@@ -0,0 +1,226 @@
+//! Bots running as child processes, speaking the [`protocol`](crate::protocol) over their stdin and stdout.
+
+use {
+    std::{
+        io::{
+            self,
+            BufReader,
+        },
+        process::{
+            Child,
+            ChildStdin,
+            ChildStdout,
+            Command,
+            Stdio,
+        },
+    },
+    serde::{
+        Serialize,
+        de::DeserializeOwned,
+    },
+    crate::{
+        frontend::{
+            Frontend,
+            lobby_input,
+        },
+        protocol::{
+            self,
+            ClientMessage,
+            ServerMessage,
+            Votes,
+            check_sender,
+            read_message,
+            write_message,
+        },
+        state::{
+            Action,
+            Event,
+            GameError,
+            IngameInput,
+            Input,
+            InputRequest,
+            PlayerId,
+            Recipient,
+            State,
+        },
+    },
+};
+
+/// How many inputs in a row a bot may get wrong before the game is stopped.
+const MAX_REJECTIONS: usize = 10;
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// A child process playing as one player.
+pub struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Process {
+    /// Spawns the command with piped stdin and stdout, and waits for it to say hello as the given player.
+    pub fn spawn<P: PlayerId + Serialize + DeserializeOwned>(command: &mut Command, player: &P) -> io::Result<Process> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut process = Process { child, stdin, stdout };
+        for _ in 0..MAX_REJECTIONS {
+            match process.recv::<P>()? {
+                Ok(ClientMessage::Hello { version, player: ref hello_player }) if version == protocol::VERSION && hello_player == player => {
+                    process.send(&ServerMessage::Welcome { version, player: player.clone() })?;
+                    return Ok(process)
+                }
+                Ok(ClientMessage::Hello { version, .. }) if version != protocol::VERSION => {
+                    let _ = process.send(&ServerMessage::<P>::Error(format!("expected protocol version {}", protocol::VERSION)));
+                    return Err(invalid_data(format!("bot speaks protocol version {}, expected {}", version, protocol::VERSION)))
+                }
+                Ok(ClientMessage::Hello { .. }) => {
+                    let _ = process.send(&ServerMessage::<P>::Error("wrong player".to_owned()));
+                    return Err(invalid_data("bot said hello as a different player"))
+                }
+                Ok(ClientMessage::Input(_)) => process.send(&ServerMessage::<P>::Error("say hello first".to_owned()))?,
+                Err(e) => process.send(&ServerMessage::<P>::Error(format!("invalid message: {}", e)))?,
+            }
+        }
+        Err(invalid_data("bot didn't say hello"))
+    }
+
+    fn send<P: PlayerId + Serialize>(&mut self, msg: &ServerMessage<P>) -> io::Result<()> {
+        write_message(&mut self.stdin, msg)
+    }
+
+    /// Reads the next message, or the error if the line isn't a valid message.
+    fn recv<P: PlayerId + DeserializeOwned>(&mut self) -> io::Result<serde_json::Result<ClientMessage<P>>> {
+        read_message(&mut self.stdout)?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "bot closed its stdout"))
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A frontend for games played entirely by bots. It joins the bots in the given order and starts the given number of segments.
+pub struct External<P: PlayerId> {
+    bots: Vec<(P, Process)>,
+    /// how many segments are left to start
+    segments: usize,
+    votes: Votes<P>,
+    /// the bot whose input was last passed to the game, to tell it if the input was rejected
+    last_sender: Option<usize>,
+    /// how many inputs in a row have been rejected
+    rejections: usize,
+    rejected_last: bool,
+}
+
+impl<P: PlayerId + Serialize + DeserializeOwned> External<P> {
+    pub fn new(bots: Vec<(P, Process)>, segments: usize) -> External<P> {
+        External {
+            bots, segments,
+            votes: Votes::default(),
+            last_sender: None,
+            rejections: 0,
+            rejected_last: false,
+        }
+    }
+
+    /// Sends the bot its view and the request, and waits for a valid answer.
+    fn ask(&mut self, state: &State<P>, ir: &InputRequest<P>, idx: usize) -> io::Result<Input<P>> {
+        let (ref player, ref mut bot) = self.bots[idx];
+        bot.send(&ServerMessage::View(state.view_for(player)))?;
+        bot.send(&ServerMessage::Request(ir.clone()))?;
+        for _ in 0..MAX_REJECTIONS {
+            match bot.recv()? {
+                Ok(ClientMessage::Input(input)) => match check_sender(state, ir, player, input) {
+                    Ok(input) => return Ok(input),
+                    Err(msg) => bot.send(&ServerMessage::<P>::Error(msg.to_owned()))?,
+                },
+                Ok(ClientMessage::Hello { .. }) => bot.send(&ServerMessage::<P>::Error("already greeted".to_owned()))?,
+                Err(e) => bot.send(&ServerMessage::<P>::Error(format!("invalid message: {}", e)))?,
+            }
+        }
+        Err(invalid_data("bot keeps giving invalid inputs"))
+    }
+
+    fn bot_idx(&self, player: &P) -> io::Result<usize> {
+        self.bots.iter().position(|(bot_player, _)| bot_player == player).ok_or_else(|| invalid_data("a player who isn't a bot was asked for input"))
+    }
+}
+
+impl<P: PlayerId + Serialize + DeserializeOwned> Frontend<P> for External<P> {
+    type Error = io::Error;
+
+    fn give_input(&mut self, state: &State<P>, ir: InputRequest<P>) -> io::Result<Option<Input<P>>> {
+        if self.rejected_last {
+            self.rejected_last = false;
+            if self.rejections >= MAX_REJECTIONS { return Err(invalid_data("too many inputs in a row were rejected")) }
+        } else {
+            self.rejections = 0;
+        }
+        self.votes.reset(&ir);
+        Ok(Some(match ir {
+            InputRequest::Meta => {
+                self.last_sender = None;
+                if let Some(input) = lobby_input(state, self.bots.iter().map(|(player, _)| player), &mut self.segments) { Input::Meta(input) } else { return Ok(None) }
+            }
+            InputRequest::Action(_, ref awaited) | InputRequest::Angel(_, _, ref awaited) => {
+                for player in awaited {
+                    let idx = self.bot_idx(player)?;
+                    self.last_sender = Some(idx);
+                    match self.ask(state, &ir, idx)? {
+                        Input::Ingame(IngameInput::Action(Action::Eat(votes))) => if let Some(eat) = self.votes.add(&ir, player.clone(), votes) {
+                            return Ok(Some(Input::Ingame(IngameInput::Action(eat))))
+                        },
+                        input => return Ok(Some(input)),
+                    }
+                }
+                return Err(invalid_data("the game is waiting for input from nobody"))
+            }
+        }))
+    }
+
+    fn notify(&mut self, recipient: Recipient<P>, event: Event<P>) -> io::Result<()> {
+        let msg = ServerMessage::Notification(recipient.clone(), event);
+        for (player, bot) in &mut self.bots {
+            if let Recipient::Player(ref recipient) = recipient {
+                if recipient != player { continue }
+            }
+            bot.send(&msg)?;
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, error: GameError) -> io::Result<()> {
+        self.rejected_last = true;
+        self.rejections += 1;
+        if let Some(idx) = self.last_sender {
+            self.bots[idx].1.send(&ServerMessage::<P>::Error(error.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[test]
+    fn handshake() {
+        Process::spawn(&mut sh(r#"echo '{"Hello":{"version":1,"player":"alice"}}'; cat > /dev/null"#), &"alice".to_owned()).unwrap();
+        // lines which aren't messages are answered with an error, without ending the session
+        Process::spawn(&mut sh(r#"echo 'hello'; read error; echo '{"Hello":{"version":1,"player":"alice"}}'; cat > /dev/null"#), &"alice".to_owned()).unwrap();
+        let e = Process::spawn(&mut sh(r#"echo '{"Hello":{"version":0,"player":"alice"}}'; cat > /dev/null"#), &"alice".to_owned()).map(|_| ()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,9 +1,142 @@
 use crate::state::{
+    Event,
+    GameError,
     Input,
     InputRequest,
+    MetaInput,
     PlayerId,
+    Recipient,
+    State,
 };
 
+/// Connects a game to its players.
+///
+/// Frontends are trusted: they get the full state, e.g. to know whom to ask for input, so they're responsible for only showing each player what [`State::view_for`] returns for them.
 pub trait Frontend<P: PlayerId> {
-    fn give_input(&mut self, ir: InputRequest) -> Input<P>;
+    type Error;
+
+    /// Returns the input answering the given request, or `None` to stop the game.
+    fn give_input(&mut self, state: &State<P>, ir: InputRequest<P>) -> Result<Option<Input<P>>, Self::Error>;
+
+    /// Called for each notification caused by an accepted input. Private notifications must only be shown to their recipient.
+    fn notify(&mut self, recipient: Recipient<P>, event: Event<P>) -> Result<(), Self::Error> {
+        let _ = (recipient, event);
+        Ok(())
+    }
+
+    /// Called when the game rejects an input, so the frontend can tell the player what went wrong.
+    fn reject(&mut self, error: GameError) -> Result<(), Self::Error> {
+        let _ = error;
+        Ok(())
+    }
+}
+
+/// Runs the game by passing the frontend's inputs to the state until the frontend stops it.
+pub fn run_game<P: PlayerId, F: Frontend<P>>(state: &mut State<P>, frontend: &mut F) -> Result<(), F::Error> {
+    while let Some(input) = frontend.give_input(state, state.next_input())? {
+        match state.advance_game(input) {
+            Ok(notifications) => for (recipient, event) in notifications {
+                frontend.notify(recipient, event)?;
+            },
+            Err(e) => frontend.reject(e)?,
+        }
+    }
+    Ok(())
+}
+
+/// Answers a meta request for a game played by a fixed list of players, e.g. bots: joins the first of them who hasn't joined yet, or else starts the next segment and counts it down. Returns `None` once there are no segments left to start.
+pub(crate) fn lobby_input<'a, P: PlayerId + 'a>(state: &State<P>, players: impl IntoIterator<Item = &'a P>, segments: &mut usize) -> Option<MetaInput<P>> {
+    if let Some(player) = players.into_iter().find(|&player| state.players().iter().all(|iter_player| iter_player.id != *player)) {
+        Some(MetaInput::Join(player.clone(), None))
+    } else if *segments == 0 {
+        None
+    } else {
+        *segments -= 1;
+        Some(MetaInput::Go)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::collections::VecDeque,
+        crate::{
+            identities::Identity,
+            roles::Role,
+            state::IngameInput,
+        },
+        super::*,
+    };
+
+    /// Gives the scripted inputs in order, then stops the game, and records everything the game tells it.
+    #[derive(Default)]
+    struct Script {
+        inputs: VecDeque<Input<usize>>,
+        requests: Vec<InputRequest<usize>>,
+        notifications: Vec<(Recipient<usize>, Event<usize>)>,
+        rejections: Vec<GameError>,
+    }
+
+    impl Frontend<usize> for Script {
+        type Error = ();
+
+        fn give_input(&mut self, _: &State<usize>, ir: InputRequest<usize>) -> Result<Option<Input<usize>>, ()> {
+            self.requests.push(ir);
+            Ok(self.inputs.pop_front())
+        }
+
+        fn notify(&mut self, recipient: Recipient<usize>, event: Event<usize>) -> Result<(), ()> {
+            self.notifications.push((recipient, event));
+            Ok(())
+        }
+
+        fn reject(&mut self, error: GameError) -> Result<(), ()> {
+            self.rejections.push(error);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn scripted_game() {
+        let mut state = State::default();
+        let mut script = Script {
+            inputs: vec![
+                Input::Meta(MetaInput::Join(0, Some(0))),
+                Input::Meta(MetaInput::Join(1, Some(1))),
+                Input::Meta(MetaInput::Join(2, Some(2))),
+                Input::Meta(MetaInput::Deal(vec![(0, Role::Seer, Identity::Romeo), (1, Role::Mayor, Identity::Juliet), (2, Role::Wolf, Identity::Single)])),
+                Input::Meta(MetaInput::Join(3, None)),
+                Input::Ingame(IngameInput::WinAttempt { claimant: 0, identity: Identity::Romeo, partners: vec![1] }),
+            ].into(),
+            ..Script::default()
+        };
+        run_game(&mut state, &mut script).unwrap();
+        assert_eq!(format!("{:?}", script.requests), format!("{:?}", vec![
+            InputRequest::Meta, InputRequest::Meta, InputRequest::Meta, InputRequest::Meta,
+            InputRequest::Action(Role::Seer, vec![0]), InputRequest::Action(Role::Seer, vec![0]),
+            InputRequest::Meta,
+        ]));
+        assert_eq!(script.rejections, vec![GameError::MetaInputIngame]);
+        assert_eq!(script.notifications[..3], [
+            (Recipient::Player(0), Event::Character(Role::Seer, Identity::Romeo)),
+            (Recipient::Player(1), Event::Character(Role::Mayor, Identity::Juliet)),
+            (Recipient::Player(2), Event::Character(Role::Wolf, Identity::Single)),
+        ]);
+        assert!(script.notifications.contains(&(Recipient::Everyone, Event::Victory(vec![0, 1]))));
+        assert_eq!(state.points().get(&0), Some(&2));
+    }
+
+    #[test]
+    fn lobby() {
+        let mut state = State::default();
+        let mut segments = 1;
+        for _ in 0..3 {
+            let input = lobby_input(&state, &[0, 1, 2], &mut segments).unwrap();
+            assert!(matches!(input, MetaInput::Join(_, None)));
+            state.advance_game(Input::Meta(input)).unwrap();
+        }
+        assert!(matches!(lobby_input(&state, &[0, 1, 2], &mut segments), Some(MetaInput::Go)));
+        assert_eq!(segments, 0);
+        assert!(lobby_input(&state, &[0, 1, 2], &mut segments).is_none());
+    }
 }
@@ -1,10 +1,12 @@
 use {
     std::{
-        convert::Infallible as Never,
+        fmt,
         hash::Hash,
+        iter,
         ops::BitOr,
     },
     enum_iterator::IntoEnumIterator,
+    itertools::Itertools as _,
     crate::{
         roles::Role,
         state::{
@@ -14,6 +16,7 @@ use {
         },
     },
     self::{
+        Condition::*,
         Identity::*,
         Party::*,
         SoloIdentity::*,
@@ -22,7 +25,8 @@ use {
 };
 
 #[derive(Debug, Clone, Copy, IntoEnumIterator, PartialEq, Eq, Hash)]
-pub(crate) enum SoloIdentity {
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SoloIdentity {
     Sherlock,
     V,
     JackTheRipper,
@@ -30,8 +34,21 @@ pub(crate) enum SoloIdentity {
     Macbeth,
 }
 
+impl fmt::Display for SoloIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sherlock => write!(f, "Sherlock"),
+            V => write!(f, "V"),
+            JackTheRipper => write!(f, "Jack the Ripper"),
+            Queen => write!(f, "Queen"),
+            Macbeth => write!(f, "Macbeth"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, IntoEnumIterator, PartialEq, Eq, Hash)]
-pub(crate) enum Identity {
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Identity {
     Solo(SoloIdentity),
     Romeo,
     Juliet,
@@ -41,8 +58,23 @@ pub(crate) enum Identity {
     Mozart,
 }
 
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Solo(solo) => solo.fmt(f),
+            Romeo => write!(f, "Romeo"),
+            Juliet => write!(f, "Juliet"),
+            Single => write!(f, "Single"),
+            Churchill => write!(f, "Churchill"),
+            Doyle => write!(f, "Doyle"),
+            Mozart => write!(f, "Mozart"),
+        }
+    }
+}
+
 impl Identity {
-    pub(crate) fn party(&self) -> Party {
+    /// The party this identity belongs to. Players win together with the other members of their party.
+    pub fn party(&self) -> Party {
         match *self {
             Solo(solo) => SoloParty(solo),
             Romeo | Juliet | Single => Lovers,
@@ -50,43 +82,113 @@ impl Identity {
         }
     }
 
-    pub(crate) fn wincon<P: PlayerId>(&self) -> Wincon<P> {
+    pub(crate) fn wincon(&self) -> Wincon {
         (match self {
             Romeo | Juliet | Single => None,
             Churchill => With(SoloParty(Queen)),
             Doyle => With(SoloParty(Sherlock)),
             Mozart => With(Lovers),
-            Solo(Queen) => Static(Box::new(|state| state.phase == Some(Role::Angel) && !state.mourning)),
-            _ => unimplemented!(), //TODO
+            // Sherlock catches Jack the Ripper
+            Solo(Sherlock) => Static(IdentityFallen(Solo(JackTheRipper))),
+            // V overthrows the Queen
+            Solo(V) => Static(IdentityFallen(Solo(Queen))),
+            // Jack the Ripper profits from a bloody night
+            Solo(JackTheRipper) => Static(Deaths(2)),
+            // the Queen enjoys a peaceful cycle
+            Solo(Queen) => Static(Peace),
+            // Macbeth murders the king
+            Solo(Macbeth) => Static(RoleFallen(Role::Mayor)),
         }) | self.party().wincon()
     }
 }
 
-type Flip = Never; // "IO Bool"
+/// A condition on the state of the game which makes a player win as soon as it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub(crate) enum Condition {
+    /// A cycle ends without anyone dying.
+    Peace,
+    /// A player with this identity dies.
+    IdentityFallen(Identity),
+    /// A player with this role dies.
+    RoleFallen(Role),
+    /// At least this many players die in a single cycle.
+    Deaths(usize),
+}
+
+impl Condition {
+    fn holds<P: PlayerId>(&self, state: &State<P>) -> bool {
+        match *self {
+            Peace => state.phase == Some(Role::Angel) && state.fallen.is_empty(),
+            IdentityFallen(fallen_identity) => state.fallen.iter().any(|&(_, identity)| identity == fallen_identity),
+            RoleFallen(fallen_role) => state.fallen.iter().any(|&(role, _)| role == fallen_role),
+            Deaths(deaths) => state.fallen.len() >= deaths,
+        }
+    }
+}
+
+/// A win condition which a player has to claim with a win attempt, by revealing themself along with partners from their party.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub(crate) struct Flip {
+    /// the party the claimant and all partners must belong to
+    party: Party,
+    /// how many partners the claimant must name
+    partners: usize,
+}
+
+impl Flip {
+    /// Checks whether the claimant and the named partners are all alive and belong to the flip's party.
+    fn check<P: PlayerId>(&self, state: &State<P>, claimant: Seat, partners: &[Seat]) -> bool {
+        partners.len() == self.partners
+        && iter::once(&claimant).chain(partners).unique().count() == self.partners + 1
+        && iter::once(&claimant).chain(partners).all(|&seat| state.players.get(seat).and_then(|player| player.character.as_ref()).map_or(false, |c| c.identity.party() == self.party))
+    }
+}
 
-pub(crate) enum Wincon<P: PlayerId> {
-    Static(Box<dyn Fn(&State<P>) -> bool>),
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub(crate) enum Wincon {
+    Static(Condition),
     Flips(Flip),
     With(Party),
     /// constructed on the fly only, with `bitor`
-    Either(Box<Wincon<P>>, Box<Wincon<P>>),
+    Either(Box<Wincon>, Box<Wincon>),
     None,
 }
 
-impl<P: PlayerId> Wincon<P> {
-    pub(crate) fn is_with(&self, state: &State<P>, victors: &[Seat]) -> bool {
+impl Wincon {
+    /// Checks whether this win condition is static and currently holds.
+    pub(crate) fn holds<P: PlayerId>(&self, state: &State<P>) -> bool {
+        match self {
+            Static(condition) => condition.holds(state),
+            Either(lhs, rhs) => lhs.holds(state) || rhs.holds(state),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_with<P: PlayerId>(&self, state: &State<P>, victors: &[Seat]) -> bool {
         match self {
             With(party) => victors.iter().any(|&seat| state.players[seat].character.as_ref().map_or(false, |c| c.identity.party() == *party)),
             Either(lhs, rhs) => lhs.is_with(state, victors) || rhs.is_with(state, victors),
             _ => false,
         }
     }
+
+    /// Checks whether a win attempt by `claimant` naming the given `partners` satisfies this win condition.
+    pub(crate) fn is_flipped<P: PlayerId>(&self, state: &State<P>, claimant: Seat, partners: &[Seat]) -> bool {
+        match self {
+            Flips(flip) => flip.check(state, claimant, partners),
+            Either(lhs, rhs) => lhs.is_flipped(state, claimant, partners) || rhs.is_flipped(state, claimant, partners),
+            _ => false,
+        }
+    }
 }
 
-impl<P: PlayerId> BitOr for Wincon<P> {
-    type Output = Wincon<P>;
+impl BitOr for Wincon {
+    type Output = Wincon;
 
-    fn bitor(self, rhs: Wincon<P>) -> Wincon<P> {
+    fn bitor(self, rhs: Wincon) -> Wincon {
         match (self, rhs) {
             (wincon, None) | (None, wincon) => wincon,
             (lhs, rhs) => Either(Box::new(lhs), Box::new(rhs)),
@@ -94,19 +196,31 @@ impl<P: PlayerId> BitOr for Wincon<P> {
     }
 }
 
-#[derive(PartialEq, Eq)]
-pub(crate) enum Party {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Party {
+    /// Each solo identity is a party of its own.
     SoloParty(SoloIdentity),
     Lovers,
     Masons,
 }
 
+impl fmt::Display for Party {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoloParty(solo) => solo.fmt(f),
+            Lovers => write!(f, "Lovers"),
+            Masons => write!(f, "Masons"),
+        }
+    }
+}
+
 impl Party {
-    fn wincon<P: PlayerId>(&self) -> Wincon<P> {
+    fn wincon(&self) -> Wincon {
         match self {
             SoloParty(_) => None,
-            Lovers => Flips(unimplemented!()), //TODO
-            Masons => Flips(unimplemented!()), //TODO
+            Lovers => Flips(Flip { party: Lovers, partners: 1 }),
+            Masons => Flips(Flip { party: Masons, partners: 2 }),
         }
     }
 }
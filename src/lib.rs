@@ -1,8 +1,15 @@
 //#![deny(missing_docs, rust_2018_idioms, unused, unused_import_braces, unused_lifetimes, unused_qualifications, warnings)]
 
+pub mod bot;
+#[cfg(feature = "protocol")] pub mod external;
 pub mod frontend;
-mod identities;
+pub mod identities;
+pub mod log;
+#[cfg(feature = "server")] pub mod net;
 pub mod roles;
 mod player;
+#[cfg(feature = "protocol")] pub mod protocol;
+pub mod rules;
 pub mod state;
 mod util;
+pub mod view;
@@ -0,0 +1,33 @@
+use crate::{
+    rules::Rules,
+    state::{
+        GameError,
+        Input,
+        PlayerId,
+        State,
+    },
+};
+
+/// Everything needed to rebuild a game: its rules, the seed for its random decisions, and all inputs applied to it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Log<P: PlayerId> {
+    pub rules: Rules,
+    pub seed: u64,
+    pub inputs: Vec<Input<P>>,
+}
+
+impl<P: PlayerId> Log<P> {
+    /// Rebuilds the game state by applying all inputs in order to a new game.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the inputs is invalid, which can only happen if the log was modified.
+    pub fn replay(&self) -> Result<State<P>, GameError> {
+        let mut state = State::with_seed(self.rules.clone(), self.seed);
+        for input in &self.inputs {
+            state.advance_game(input.clone())?;
+        }
+        Ok(state)
+    }
+}
@@ -2,6 +2,7 @@
 
 use {
     std::{
+        collections::HashMap,
         fmt,
         io::{
             self,
@@ -10,46 +11,508 @@ use {
             stdout,
         },
     },
+    enum_iterator::IntoEnumIterator as _,
+    itertools::Itertools as _,
     structopt::StructOpt,
-    victory::state::{
-        Input,
-        InputRequest,
-        MetaInput,
-        State,
+    victory::{
+        bot::Difficulty,
+        frontend::{
+            Frontend,
+            run_game,
+        },
+        identities::Identity,
+        roles::Role,
+        rules::Rules,
+        state::{
+            Action,
+            Event,
+            GameError,
+            IngameInput,
+            Input,
+            InputRequest,
+            Insight,
+            MetaInput,
+            Position,
+            Recipient,
+            State,
+        },
     },
 };
 
+mod simulate;
+#[cfg(feature = "tui")] mod tui;
+
+#[cfg(feature = "protocol")] use victory::external::{
+    External,
+    Process,
+};
+
 #[derive(StructOpt)]
 struct Args {
     #[structopt(long = "debug")]
     debug: bool,
+    /// Pass-and-play mode: hide each player's private information from everyone else at the table
+    #[structopt(long = "hot-seat")]
+    hot_seat: bool,
+    /// Narrator mode for games played with physical cards: enter the dealt cards and what happens, and get told whom to wake
+    #[structopt(long = "narrate", conflicts_with = "hot-seat")]
+    narrate: bool,
+    /// Full-screen terminal interface for a moderator
+    #[cfg(feature = "tui")]
+    #[structopt(long = "tui", conflicts_with_all = &["hot-seat", "narrate"])]
+    tui: bool,
+    /// Play a game between bots instead, each given as NAME=COMMAND. The commands are run as child processes speaking the JSON lines protocol
+    #[cfg(feature = "protocol")]
+    #[structopt(long = "bot", conflicts_with_all = &["hot-seat", "narrate"])]
+    bots: Vec<BotArg>,
+    /// How many segments the bots play
+    #[cfg(feature = "protocol")]
+    #[structopt(long = "segments", default_value = "1")]
+    segments: usize,
+    /// Seed for all random decisions, to make the game reproducible
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+    #[structopt(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Play many games between built-in bots and report statistics, to help balance the deck
+    Simulate {
+        /// How many players sit at the table
+        #[structopt(long = "players")]
+        players: usize,
+        /// How many games (of one segment each) to play
+        #[structopt(long = "games", default_value = "1000")]
+        games: usize,
+        /// How many hunters are in the deck
+        #[structopt(long = "hunters", default_value = "1")]
+        hunters: usize,
+        /// How cleverly the bots play, either easy or normal
+        #[structopt(long = "difficulty", default_value = "normal", parse(try_from_str = parse_difficulty))]
+        difficulty: Difficulty,
+    },
+}
+
+fn parse_difficulty(s: &str) -> Result<Difficulty, &'static str> {
+    match &*s.to_ascii_lowercase() {
+        "easy" => Ok(Difficulty::Easy),
+        "normal" => Ok(Difficulty::Normal),
+        _ => Err("expected easy or normal"),
+    }
 }
 
+#[cfg(feature = "protocol")]
+struct BotArg {
+    name: String,
+    command: Vec<String>,
+}
+
+#[cfg(feature = "protocol")]
+impl std::str::FromStr for BotArg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<BotArg, &'static str> {
+        let (name, command) = s.split_at(s.find('=').ok_or("expected NAME=COMMAND")?);
+        let command = command[1..].split_whitespace().map(str::to_owned).collect_vec();
+        if name.is_empty() || command.is_empty() { return Err("expected NAME=COMMAND") }
+        Ok(BotArg { name: name.to_owned(), command })
+    }
+}
+
+/// Returns an error of kind `UnexpectedEof` at the end of input, which ends the game.
 fn input(prompt: impl fmt::Display) -> io::Result<String> {
     print!("{}: ", prompt);
     stdout().flush()?;
     let mut buf = String::default();
-    stdin().read_line(&mut buf)?;
+    if stdin().read_line(&mut buf)? == 0 { return Err(io::ErrorKind::UnexpectedEof.into()) }
     Ok(buf.trim().to_owned())
 }
 
-#[paw::main]
-fn main(args: Args) -> io::Result<()> {
-    let mut state = State::default();
+/// Asks until one of the options is entered. Leaving the answer blank picks the first option.
+fn choice<'a>(prompt: impl fmt::Display, options: &[&'a str]) -> io::Result<&'a str> {
+    loop {
+        let answer = input(format_args!("{} [{}]", prompt, options.join("/")))?;
+        if answer.is_empty() { return Ok(options[0]) }
+        if let Some(&option) = options.iter().find(|option| option.eq_ignore_ascii_case(&answer)) { return Ok(option) }
+        eprintln!("please enter one of: {}", options.join(", "));
+    }
+}
+
+fn yes_no(prompt: impl fmt::Display) -> io::Result<bool> {
+    Ok(choice(prompt, &["n", "y"])? == "y")
+}
+
+/// Asks for a player name until one at the table is entered, and returns their seat. Leaving the answer blank returns `None`.
+fn opt_seat(state: &State<String>, prompt: impl fmt::Display) -> io::Result<Option<usize>> {
+    loop {
+        let name = input(&prompt)?;
+        if name.is_empty() { return Ok(None) }
+        if let Some(seat) = state.players().iter().position(|player| player.id == name) { return Ok(Some(seat)) }
+        eprintln!("there is no player named {:?}", name);
+    }
+}
+
+fn seat(state: &State<String>, prompt: impl fmt::Display) -> io::Result<usize> {
+    loop {
+        if let Some(seat) = opt_seat(state, &prompt)? { return Ok(seat) }
+    }
+}
+
+fn role(prompt: impl fmt::Display) -> io::Result<Role> {
+    loop {
+        let name = input(&prompt)?;
+        if let Some(role) = Role::into_enum_iter().find(|role| role.to_string().eq_ignore_ascii_case(&name)) { return Ok(role) }
+        eprintln!("please enter one of: {}", Role::into_enum_iter().join(", "));
+    }
+}
+
+fn identity(prompt: impl fmt::Display) -> io::Result<Identity> {
     loop {
-        if args.debug { eprintln!("{:#?}", state) }
-        state.advance_game(match state.next_input() {
-            InputRequest::Meta => Input::Meta({
-                let name = input("enter player name to add/remove [leave blank to start]")?;
-                if name.is_empty() {
-                    MetaInput::Go
-                } else if state.players().iter().any(|player| player.id == name) {
-                    MetaInput::Quit(name)
+        let name = input(&prompt)?;
+        if let Some(identity) = Identity::into_enum_iter().find(|identity| identity.to_string().eq_ignore_ascii_case(&name)) { return Ok(identity) }
+        eprintln!("please enter one of: {}", Identity::into_enum_iter().join(", "));
+    }
+}
+
+fn print_table(state: &State<String>) {
+    let view = state.spectator_view();
+    println!("players: {}", view.players.iter().map(|(name, alive)| {
+        let points = view.points.get(name).copied().unwrap_or_default();
+        if *alive { format!("{} ({} points)", name, points) } else { format!("{} ({} points, dead)", name, points) }
+    }).join(", "));
+}
+
+fn describe_event(event: Event<String>) -> String {
+    match event {
+        Event::Character(role, identity) => format!("you are the {} and {}", role, identity),
+        Event::Bitten => "you have been bitten by the vampire".to_owned(),
+        Event::Insight(Insight::Identity(name, identity)) => format!("{} is {}", name, identity),
+        Event::Insight(Insight::Role(name, role)) => format!("{} is the {}", name, role),
+        Event::Died(name) => format!("{} has died", name),
+        Event::Victory(names) => format!("{} won", names.join(", ")),
+        Event::PointsWon(points) => format!("you won {} points", points),
+        Event::FalseClaim(name) => format!("{}'s win attempt failed", name),
+        Event::PointsLost(points) => format!("you lost {} points", points),
+    }
+}
+
+fn describe_action(state: &State<String>, action: &Action) -> String {
+    let name = |seat: usize| &state.players()[seat].id;
+    match *action {
+        Action::VoteResult(None) => "nobody is executed".to_owned(),
+        Action::VoteResult(Some(seat)) => format!("{} is executed", name(seat)),
+        Action::Shoot(hunter) => format!("{} shoots", name(hunter)),
+        Action::Aim(hunter, target) => format!("{} aims at {}", name(hunter), name(target)),
+        Action::Bite(seat) => format!("the vampire bites {}", name(seat)),
+        Action::Swap(seat0, seat1) => format!("the jester swaps {} and {}", name(seat0), name(seat1)),
+        Action::Inspect(seat) => format!("the seer inspects {}", name(seat)),
+        Action::Eat(ref votes) => format!("the wolves vote to eat {}", votes.iter().map(|(&wolf, &target)| format!("{} ({})", name(target), name(wolf))).join(", ")),
+        Action::Potions { heal, poison } => match (heal, poison) {
+            (false, None) => "the witch passes".to_owned(),
+            (true, None) => "the witch heals".to_owned(),
+            (false, Some(seat)) => format!("the witch poisons {}", name(seat)),
+            (true, Some(seat)) => format!("the witch heals and poisons {}", name(seat)),
+        },
+    }
+}
+
+fn clear_screen() -> io::Result<()> {
+    print!("\x1b[2J\x1b[H");
+    stdout().flush()
+}
+
+#[derive(Default)]
+struct Cli {
+    debug: bool,
+    hot_seat: bool,
+    /// in hot-seat mode, private notifications not yet shown to their recipients
+    private: HashMap<String, Vec<String>>,
+    /// in hot-seat mode, whether everyone should see their newly dealt character before the next in-game input
+    dealing: bool,
+    /// in hot-seat mode, the players who gave the last input in private, who are told privately if it's rejected
+    actors: Vec<String>,
+    narrate: bool,
+    /// in narrator mode, the position of the previous in-game input, to tell the narrator which phases were skipped
+    last_position: Option<Position>,
+}
+
+impl Cli {
+    fn meta_input(&self, state: &State<String>) -> io::Result<MetaInput<String>> {
+        if self.narrate { return self.narrator_meta_input(state) }
+        let name = input("enter player name to add/remove [leave blank to start]")?;
+        Ok(if name.is_empty() {
+            MetaInput::Go
+        } else if state.players().iter().any(|player| player.id == name) {
+            MetaInput::Quit(name)
+        } else {
+            MetaInput::Join(name, None)
+        })
+    }
+
+    /// Players join in seating order, and the narrator enters the cards each player has been dealt.
+    fn narrator_meta_input(&self, state: &State<String>) -> io::Result<MetaInput<String>> {
+        let name = input("enter player name to add/remove, in seating order [leave blank to deal]")?;
+        Ok(if name.is_empty() {
+            let mut characters = Vec::default();
+            for (seat, (name, alive)) in state.spectator_view().players.into_iter().enumerate() {
+                if !alive {
+                    characters.push((seat, role(format_args!("{}'s role", name))?, identity(format_args!("{}'s identity", name))?));
+                }
+            }
+            MetaInput::Deal(characters)
+        } else if state.players().iter().any(|player| player.id == name) {
+            MetaInput::Quit(name)
+        } else {
+            MetaInput::Join(name, Some(state.players().len()))
+        })
+    }
+
+    fn ingame_input(&mut self, state: &State<String>, phase: Role, pending: Option<Action>, awaited: Vec<String>) -> io::Result<IngameInput> {
+        if self.hot_seat { return self.hot_seat_input(state, phase, pending, awaited) }
+        if self.narrate { return self.narrator_input(state, phase, pending, awaited) }
+        print_table(state);
+        if let Some(ref action) = pending {
+            println!("{} phase, waiting for {} to pass or veto: {}", phase, awaited.join(", "), describe_action(state, action));
+            self.angel_input(state, true)
+        } else {
+            println!("{} phase, waiting for {}", phase, awaited.join(", "));
+            self.action_input(state, phase, true)
+        }
+    }
+
+    /// Lets the players pass the device around so that only the player holding it sees their prompt and private information.
+    ///
+    /// In every phase, the device goes to each living player in seating order, and those who aren't acting get a prompt without effect, so nobody can tell who is acting.
+    fn hot_seat_input(&mut self, state: &State<String>, phase: Role, pending: Option<Action>, awaited: Vec<String>) -> io::Result<IngameInput> {
+        if self.dealing {
+            self.dealing = false;
+            for player in state.players() {
+                self.hand_over(state, &player.id)?;
+                self.hide()?;
+            }
+        }
+        loop {
+            clear_screen()?;
+            print_table(state);
+            println!("{} phase", phase);
+            match choice("continue, let a player look at their private information, or attempt a win", &["continue", "peek", "claim"])? {
+                "continue" => break,
+                "peek" => {
+                    let name = seat(state, "player")?;
+                    self.hand_over(state, &state.players()[name].id)?;
+                    self.hide()?;
+                }
+                _ => {
+                    self.actors.clear();
+                    return self.win_attempt(state)
+                }
+            }
+        }
+        // all wolves vote, but only one player decides other actions
+        let wolf_vote = phase == Role::Wolf && pending.is_none();
+        let mut input = None;
+        let mut votes = HashMap::default();
+        self.actors.clear();
+        for (name, _) in state.spectator_view().players.into_iter().filter(|&(_, alive)| alive) {
+            self.hand_over(state, &name)?;
+            if awaited.contains(&name) && (wolf_vote || input.is_none()) {
+                self.actors.push(name.clone());
+                if pending.is_some() {
+                    input = Some(self.angel_input(state, false)?);
+                } else if wolf_vote {
+                    let view = state.view_for(&name);
+                    for &wolf in &view.wolves {
+                        if view.acts_for(wolf) {
+                            votes.insert(wolf, seat(state, format_args!("player to eat for {}", state.players()[wolf].id))?);
+                        }
+                    }
                 } else {
-                    MetaInput::Join(name, None)
+                    input = Some(self.action_input(state, phase, false)?);
                 }
+            } else {
+                seat(state, "you can't act right now, enter any player's name to continue")?;
+            }
+            self.hide()?;
+        }
+        Ok(input.unwrap_or(IngameInput::Action(Action::Eat(votes))))
+    }
+
+    /// Tells the narrator whom to wake, including phases in which nobody can act, so the narrator can call them anyway.
+    fn narrator_input(&mut self, state: &State<String>, phase: Role, pending: Option<Action>, awaited: Vec<String>) -> io::Result<IngameInput> {
+        let position = state.position().expect("in-game input requested between segments");
+        let skipped = position.skipped_since(self.last_position);
+        if !skipped.is_empty() { println!("nobody can act in these phases, call them anyway: {}", skipped.iter().join(", ")) }
+        self.last_position = Some(position);
+        print_table(state);
+        if let Some(ref action) = pending {
+            println!("wake the angel ({}) and show them the {} phase's action: {}", awaited.join(", "), phase, describe_action(state, action));
+            self.angel_input(state, true)
+        } else {
+            println!("wake the {} ({})", phase, awaited.join(", "));
+            self.action_input(state, phase, true)
+        }
+    }
+
+    /// Asks the given player to take the device, then shows them their private information.
+    fn hand_over(&mut self, state: &State<String>, name: &str) -> io::Result<()> {
+        clear_screen()?;
+        while input(format_args!("pass the device to {}, who should enter their name to confirm", name))? != name {}
+        let view = state.view_for(&name.to_owned());
+        let name_of = |seat: usize| &state.players()[seat].id;
+        if let Some(c) = view.character {
+            println!("you are the {} and {}", c.role, c.identity);
+            if c.bitten { println!("you have been bitten by the vampire") }
+            if let Some((health_potion, poison_potion)) = c.potions { println!("health potion: {}, poison potion: {}", health_potion, poison_potion) }
+            if let Some(target) = c.target { println!("you are aiming at {}", name_of(target)) }
+        } else {
+            println!("you are dead");
+        }
+        for insight in view.insights {
+            match insight {
+                Insight::Identity(name, identity) => println!("{} is {}", name, identity),
+                Insight::Role(name, role) => println!("{} is the {}", name, role),
+            }
+        }
+        for (seat, role) in view.bitten { println!("you act for {}, the {}", name_of(seat), role) }
+        if !view.wolves.is_empty() { println!("wolves: {}", view.wolves.into_iter().map(name_of).join(", ")) }
+        if let Some(dying) = view.dying { println!("{} has been eaten by the wolves", name_of(dying)) }
+        if let Some(ref action) = view.pending_action { println!("pending action: {}", describe_action(state, action)) }
+        for text in self.private.remove(name).unwrap_or_default() { println!("{}", text) }
+        Ok(())
+    }
+
+    fn hide(&self) -> io::Result<()> {
+        input("press enter to hide your information")?;
+        clear_screen()
+    }
+
+    fn angel_input(&self, state: &State<String>, claim: bool) -> io::Result<IngameInput> {
+        Ok(match choice("angel", if claim { &["pass", "veto", "claim"] } else { &["pass", "veto"] })? {
+            "pass" => IngameInput::AngelPass,
+            "veto" => IngameInput::AngelVeto(seat(state, "vetoed player")?),
+            _ => self.win_attempt(state)?,
+        })
+    }
+
+    fn action_input(&self, state: &State<String>, phase: Role, claim: bool) -> io::Result<IngameInput> {
+        let options: &[&str] = match phase {
+            Role::Hunter => &["aim", "shoot", "claim"],
+            Role::Vampire => &["bite", "claim"],
+            Role::Jester => &["swap", "claim"],
+            Role::Seer => &["inspect", "claim"],
+            Role::Wolf => &["eat", "claim"],
+            Role::Witch => &["potions", "claim"],
+            Role::Mayor => &["vote", "claim"],
+            Role::Angel => &["claim"],
+        };
+        let options = if claim { options } else { &options[..options.len() - 1] };
+        Ok(match choice("action", options)? {
+            "aim" => IngameInput::Action(Action::Aim(seat(state, "hunter")?, seat(state, "target")?)),
+            "shoot" => IngameInput::Action(Action::Shoot(seat(state, "hunter")?)),
+            "bite" => IngameInput::Action(Action::Bite(seat(state, "bitten player")?)),
+            "swap" => IngameInput::Action(Action::Swap(seat(state, "first player")?, seat(state, "second player")?)),
+            "inspect" => IngameInput::Action(Action::Inspect(seat(state, "inspected player")?)),
+            "eat" => {
+                let mut votes = HashMap::default();
+                while let Some(wolf) = opt_seat(state, "wolf [leave blank when all wolves have voted]")? {
+                    votes.insert(wolf, seat(state, "eaten player")?);
+                }
+                IngameInput::Action(Action::Eat(votes))
+            }
+            "potions" => IngameInput::Action(Action::Potions {
+                heal: yes_no("use the health potion")?,
+                poison: opt_seat(state, "poisoned player [leave blank to keep the poison potion]")?,
             }),
-            _ => unimplemented!(), //TODO
+            "vote" => IngameInput::Action(Action::VoteResult(opt_seat(state, "executed player [leave blank for nobody]")?)),
+            _ => self.win_attempt(state)?,
         })
     }
+
+    fn win_attempt(&self, state: &State<String>) -> io::Result<IngameInput> {
+        let claimant = seat(state, "claimant")?;
+        let identity = identity("claimed identity")?;
+        let mut partners = Vec::default();
+        while let Some(partner) = opt_seat(state, "partner [leave blank when done]")? {
+            partners.push(partner);
+        }
+        Ok(IngameInput::WinAttempt { claimant, identity, partners })
+    }
+}
+
+impl Frontend<String> for Cli {
+    type Error = io::Error;
+
+    fn give_input(&mut self, state: &State<String>, ir: InputRequest<String>) -> io::Result<Option<Input<String>>> {
+        if self.debug { eprintln!("{:#?}", state) }
+        let result = match ir {
+            InputRequest::Meta => {
+                self.dealing = true;
+                self.last_position = None;
+                self.meta_input(state).map(Input::Meta)
+            }
+            InputRequest::Action(phase, awaited) => self.ingame_input(state, phase, None, awaited).map(Input::Ingame),
+            InputRequest::Angel(phase, action, awaited) => self.ingame_input(state, phase, Some(action), awaited).map(Input::Ingame),
+        };
+        match result {
+            Ok(input) => Ok(Some(input)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn notify(&mut self, recipient: Recipient<String>, event: Event<String>) -> io::Result<()> {
+        // in hot-seat mode, the character is always shown when handing over the device, and in narrator mode, the cards have been dealt by hand
+        if let (true, Recipient::Player(_), Event::Character(..)) = (self.hot_seat || self.narrate, &recipient, &event) { return Ok(()) }
+        let text = describe_event(event);
+        match recipient {
+            Recipient::Everyone => if self.narrate { println!("announce: {}", text) } else { println!("{}", text) },
+            Recipient::Player(name) => if self.hot_seat {
+                self.private.entry(name).or_default().push(text);
+            } else if self.narrate {
+                println!("tell {} privately: {}", name, text);
+            } else {
+                println!("[for {} only] {}", name, text);
+            },
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, error: GameError) -> io::Result<()> {
+        if self.hot_seat && !self.actors.is_empty() {
+            // the error could tell everyone who acted
+            eprintln!("the input was rejected, the device goes around again");
+            for name in self.actors.drain(..) {
+                self.private.entry(name).or_default().push(format!("your input was rejected: {}", error));
+            }
+        } else {
+            eprintln!("{}", error);
+        }
+        Ok(())
+    }
+}
+
+#[paw::main]
+fn main(args: Args) -> io::Result<()> {
+    if let Some(Subcommand::Simulate { players, games, hunters, difficulty }) = args.subcommand {
+        return simulate::run(players, games, Rules { hunters, ..Rules::default() }, difficulty, args.seed.unwrap_or_else(rand::random))
+    }
+    let mut state = if let Some(seed) = args.seed { State::with_seed(Rules::default(), seed) } else { State::default() };
+    #[cfg(feature = "tui")] {
+        if args.tui { return run_game(&mut state, &mut tui::Tui::new()?) }
+    }
+    #[cfg(feature = "protocol")] {
+        if !args.bots.is_empty() {
+            let mut bots = Vec::default();
+            for BotArg { name, command } in args.bots {
+                let process = Process::spawn(std::process::Command::new(&command[0]).args(&command[1..]), &name)?;
+                bots.push((name, process));
+            }
+            run_game(&mut state, &mut External::new(bots, args.segments))?;
+            print_table(&state);
+            return Ok(())
+        }
+    }
+    run_game(&mut state, &mut Cli { debug: args.debug, hot_seat: args.hot_seat, narrate: args.narrate, ..Cli::default() })
 }
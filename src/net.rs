@@ -0,0 +1,395 @@
+//! Playing over the network: a [`Frontend`] that hosts the game for clients connecting via TCP, and a matching client. They speak the [`protocol`](crate::protocol).
+//!
+//! Each player can only be connected once at a time. If a player disconnects while the game is waiting for them, the server passes for them, see [`Server`].
+
+use {
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        io::{
+            self,
+            BufReader,
+        },
+        net::{
+            Shutdown,
+            TcpListener,
+            TcpStream,
+            ToSocketAddrs,
+        },
+        sync::mpsc::{
+            Receiver,
+            Sender,
+            channel,
+        },
+        thread,
+    },
+    itertools::Itertools as _,
+    serde::{
+        Serialize,
+        de::DeserializeOwned,
+    },
+    crate::{
+        bot::{
+            Bot,
+            Difficulty,
+        },
+        frontend::Frontend,
+        protocol::{
+            self,
+            ClientMessage,
+            ServerMessage,
+            Votes,
+            check_sender,
+            read_message,
+            write_message,
+        },
+        state::{
+            Action,
+            Event,
+            GameError,
+            IngameInput,
+            Input,
+            InputRequest,
+            PlayerId,
+            Recipient,
+            State,
+        },
+    },
+};
+
+/// What the connection threads tell the server.
+enum Incoming<P: PlayerId> {
+    Connected(usize, P, TcpStream),
+    Message(usize, Input<P>),
+    /// The client sent something other than an input, which is answered with this error.
+    Invalid(usize, String),
+    Disconnected(usize),
+}
+
+/// Hosts a game for clients connecting via TCP.
+///
+/// If an awaited player has disconnected, the server gives the input for them which a [`Bot`] falls back on, e.g. passing, so the game doesn't wait for them forever. They can reconnect at any time.
+pub struct Server<P: PlayerId> {
+    incoming: Receiver<Incoming<P>>,
+    /// the player and stream of each connection which has said hello, keyed by connection id
+    clients: HashMap<usize, (P, TcpStream)>,
+    /// players who have disconnected and not reconnected
+    left: HashSet<P>,
+    /// whether any client has connected yet, so the game can end when all clients are gone
+    had_clients: bool,
+    /// the connection whose input was last passed to the game, to tell it if the input was rejected
+    last_sender: Option<usize>,
+    /// whether the last input was given for a player who has disconnected
+    passed_for_left: bool,
+    /// the wolves' votes received so far during this wolf phase
+    votes: Votes<P>,
+}
+
+impl<P: PlayerId + Serialize + DeserializeOwned + Send + 'static> Server<P> {
+    /// Starts accepting connections on the given listener.
+    pub fn new(listener: TcpListener) -> Server<P> {
+        let (tx, incoming) = channel();
+        thread::spawn(move || for (conn_id, stream) in listener.incoming().enumerate() {
+            if let Ok(stream) = stream {
+                let tx = tx.clone();
+                thread::spawn(move || handle_connection(conn_id, stream, tx));
+            }
+        });
+        Server {
+            incoming,
+            clients: HashMap::default(),
+            left: HashSet::default(),
+            had_clients: false,
+            last_sender: None,
+            passed_for_left: false,
+            votes: Votes::default(),
+        }
+    }
+
+    fn send(&mut self, conn_id: usize, msg: &ServerMessage<P>) {
+        if let Some((_, stream)) = self.clients.get_mut(&conn_id) {
+            if write_message(stream, msg).is_err() { self.disconnect(conn_id) }
+        }
+    }
+
+    fn disconnect(&mut self, conn_id: usize) {
+        if let Some((player, _)) = self.clients.remove(&conn_id) { self.left.insert(player); }
+    }
+
+    /// Gives the input for awaited players who have disconnected, using a bot's fallback choice. Returns `None` if there's nobody to pass for, or if it's the wolf phase and connected players still have to vote.
+    fn pass_for_left(&mut self, state: &State<P>, ir: &InputRequest<P>) -> Option<Input<P>> {
+        let awaited = match *ir {
+            InputRequest::Meta => return None,
+            InputRequest::Action(_, ref awaited) | InputRequest::Angel(_, _, ref awaited) => awaited,
+        };
+        for player in awaited {
+            if !self.left.contains(player) || self.votes.contains(player) { continue }
+            let input = Bot::new(player.clone(), Difficulty::Easy, 0).fallback(&state.view_for(player), ir)?;
+            self.last_sender = None;
+            self.passed_for_left = true;
+            match input {
+                Input::Ingame(IngameInput::Action(Action::Eat(votes))) => if let Some(eat) = self.votes.add(ir, player.clone(), votes) {
+                    return Some(Input::Ingame(IngameInput::Action(eat)))
+                },
+                input => return Some(input),
+            }
+        }
+        None
+    }
+
+    fn send_to_player(&mut self, player: &P, msg: &ServerMessage<P>) {
+        let conn_ids = self.clients.iter().filter(|(_, (iter_player, _))| iter_player == player).map(|(&conn_id, _)| conn_id).collect_vec();
+        for conn_id in conn_ids { self.send(conn_id, msg) }
+    }
+
+    /// Sends the client its view, and the input request if it's addressed to it.
+    fn prompt(&mut self, conn_id: usize, state: &State<P>, ir: &InputRequest<P>) {
+        let player = if let Some((player, _)) = self.clients.get(&conn_id) { player.clone() } else { return };
+        self.send(conn_id, &ServerMessage::View(state.view_for(&player)));
+        let addressed = match *ir {
+            InputRequest::Meta => true,
+            InputRequest::Action(_, ref awaited) | InputRequest::Angel(_, _, ref awaited) => awaited.contains(&player),
+        };
+        if addressed { self.send(conn_id, &ServerMessage::Request(ir.clone())) }
+    }
+}
+
+impl<P: PlayerId + Serialize + DeserializeOwned + Send + 'static> Frontend<P> for Server<P> {
+    type Error = io::Error;
+
+    /// Waits for a valid input from one of the clients. Returns `None` once all clients have disconnected.
+    fn give_input(&mut self, state: &State<P>, ir: InputRequest<P>) -> io::Result<Option<Input<P>>> {
+        self.votes.reset(&ir);
+        self.passed_for_left = false;
+        let conn_ids = self.clients.keys().copied().collect_vec();
+        for conn_id in conn_ids { self.prompt(conn_id, state, &ir) }
+        loop {
+            if self.had_clients && self.clients.is_empty() { return Ok(None) }
+            if let Some(input) = self.pass_for_left(state, &ir) { return Ok(Some(input)) }
+            match self.incoming.recv() {
+                Ok(Incoming::Connected(conn_id, player, mut stream)) => {
+                    if self.clients.values().any(|(iter_player, _)| *iter_player == player) {
+                        // otherwise, anyone could see the player's private information and act for them
+                        let _ = write_message(&mut stream, &ServerMessage::<P>::Error("this player is already connected".to_owned()));
+                        let _ = stream.shutdown(Shutdown::Both);
+                        continue
+                    }
+                    if write_message(&mut stream, &ServerMessage::Welcome { version: protocol::VERSION, player: player.clone() }).is_err() { continue }
+                    self.left.remove(&player);
+                    self.clients.insert(conn_id, (player, stream));
+                    self.had_clients = true;
+                    self.prompt(conn_id, state, &ir);
+                }
+                Ok(Incoming::Message(conn_id, input)) => {
+                    let player = if let Some((player, _)) = self.clients.get(&conn_id) { player.clone() } else { continue };
+                    match check_sender(state, &ir, &player, input) {
+                        Ok(Input::Ingame(IngameInput::Action(Action::Eat(votes)))) => if let Some(eat) = self.votes.add(&ir, player, votes) {
+                            self.last_sender = Some(conn_id);
+                            return Ok(Some(Input::Ingame(IngameInput::Action(eat))))
+                        },
+                        Ok(input) => {
+                            self.last_sender = Some(conn_id);
+                            return Ok(Some(input))
+                        }
+                        Err(msg) => self.send(conn_id, &ServerMessage::Error(msg.to_owned())),
+                    }
+                }
+                Ok(Incoming::Invalid(conn_id, msg)) => self.send(conn_id, &ServerMessage::Error(msg)),
+                Ok(Incoming::Disconnected(conn_id)) => self.disconnect(conn_id),
+                Err(_) => return Err(io::Error::other("stopped accepting connections")),
+            }
+        }
+    }
+
+    fn notify(&mut self, recipient: Recipient<P>, event: Event<P>) -> io::Result<()> {
+        let msg = ServerMessage::Notification(recipient.clone(), event);
+        match recipient {
+            Recipient::Everyone => {
+                let conn_ids = self.clients.keys().copied().collect_vec();
+                for conn_id in conn_ids { self.send(conn_id, &msg) }
+            }
+            Recipient::Player(ref player) => self.send_to_player(player, &msg),
+        }
+        Ok(())
+    }
+
+    /// Stops the game with the error if an input given for a player who has disconnected was rejected, since the same input would be given again.
+    fn reject(&mut self, error: GameError) -> io::Result<()> {
+        if self.passed_for_left { return Err(io::Error::other(error)) }
+        if let Some(conn_id) = self.last_sender.take() { self.send(conn_id, &ServerMessage::Error(error.to_string())) }
+        Ok(())
+    }
+}
+
+fn handle_connection<P: PlayerId + Serialize + DeserializeOwned>(conn_id: usize, stream: TcpStream, tx: Sender<Incoming<P>>) {
+    let mut writer = if let Ok(writer) = stream.try_clone() { writer } else { return };
+    let mut reader = BufReader::new(stream);
+    let player = loop {
+        let error = match read_message::<ClientMessage<P>>(&mut reader) {
+            Ok(Some(Ok(ClientMessage::Hello { version, player }))) => if version == protocol::VERSION {
+                break player
+            } else {
+                let _ = write_message(&mut writer, &ServerMessage::<P>::Error(format!("this server speaks protocol version {}", protocol::VERSION)));
+                return
+            },
+            Ok(Some(Ok(ClientMessage::Input(_)))) => "say hello first".to_owned(),
+            Ok(Some(Err(e))) => format!("invalid message: {}", e),
+            Ok(None) | Err(_) => return,
+        };
+        if write_message(&mut writer, &ServerMessage::<P>::Error(error)).is_err() { return }
+    };
+    // the server welcomes the player unless they're already connected
+    if tx.send(Incoming::Connected(conn_id, player, writer)).is_err() { return }
+    loop {
+        let msg = match read_message(&mut reader) {
+            Ok(Some(Ok(ClientMessage::Input(input)))) => Incoming::Message(conn_id, input),
+            Ok(Some(Ok(ClientMessage::Hello { .. }))) => Incoming::Invalid(conn_id, "already greeted".to_owned()),
+            Ok(Some(Err(e))) => Incoming::Invalid(conn_id, format!("invalid message: {}", e)),
+            Ok(None) | Err(_) => break,
+        };
+        if tx.send(msg).is_err() { return }
+    }
+    let _ = tx.send(Incoming::Disconnected(conn_id));
+}
+
+/// A connection to a [`Server`], for one player.
+pub struct Client<P: PlayerId> {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    player: P,
+}
+
+impl<P: PlayerId + Serialize + DeserializeOwned> Client<P> {
+    /// Connects to the server and waits for it to accept the player.
+    pub fn connect(addr: impl ToSocketAddrs, player: P) -> io::Result<Client<P>> {
+        let mut writer = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(writer.try_clone()?);
+        write_message(&mut writer, &ClientMessage::Hello { version: protocol::VERSION, player: player.clone() })?;
+        match read_message::<ServerMessage<P>>(&mut reader)?.transpose()? {
+            Some(ServerMessage::Welcome { .. }) => Ok(Client { writer, reader, player }),
+            Some(ServerMessage::Error(msg)) => Err(io::Error::new(io::ErrorKind::ConnectionRefused, msg)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected welcome message")),
+        }
+    }
+
+    pub fn player(&self) -> &P { &self.player }
+
+    pub fn send(&mut self, input: Input<P>) -> io::Result<()> {
+        write_message(&mut self.writer, &ClientMessage::Input(input))
+    }
+
+    /// Waits for the next message from the server. Returns `None` if the server has closed the connection.
+    pub fn recv(&mut self) -> io::Result<Option<ServerMessage<P>>> {
+        Ok(read_message(&mut self.reader)?.transpose()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::io::prelude::*,
+        crate::{
+            frontend::run_game,
+            identities::Identity,
+            roles::Role,
+            rules::Rules,
+            state::MetaInput,
+        },
+        super::*,
+    };
+
+    /// Reads messages until the predicate matches one, and returns that message.
+    fn recv_until(client: &mut Client<String>, mut pred: impl FnMut(&ServerMessage<String>) -> bool) -> ServerMessage<String> {
+        loop {
+            let msg = client.recv().unwrap().expect("server closed the connection");
+            if pred(&msg) { return msg }
+        }
+    }
+
+    #[test]
+    fn loopback_game() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut state = State::<String>::with_seed(Rules::default(), 0);
+            run_game(&mut state, &mut Server::new(listener)).unwrap();
+            state
+        });
+        let mut clients = ["alice", "bob", "carol"].iter().map(|name| Client::connect(addr, name.to_string()).unwrap()).collect_vec();
+        for client in &mut clients {
+            let player = client.player().clone();
+            recv_until(client, |msg| matches!(msg, ServerMessage::Request(InputRequest::Meta)));
+            client.send(Input::Meta(MetaInput::Join(player.clone(), None))).unwrap();
+            recv_until(client, |msg| matches!(msg, ServerMessage::View(view) if view.players.iter().any(|(iter_player, _)| *iter_player == player)));
+        }
+        // clients can't act for each other
+        clients[0].send(Input::Meta(MetaInput::Quit("bob".to_owned()))).unwrap();
+        recv_until(&mut clients[0], |msg| matches!(msg, ServerMessage::Error(_)));
+        clients[0].send(Input::Meta(MetaInput::Go)).unwrap();
+        for client in &mut clients {
+            // each client is told their own character, and nobody else's
+            let player = client.player().clone();
+            match recv_until(client, |msg| matches!(msg, ServerMessage::Notification(..))) {
+                ServerMessage::Notification(Recipient::Player(recipient), Event::Character(_, _)) => assert_eq!(recipient, player),
+                msg => panic!("unexpected message: {:?}", msg),
+            }
+            match recv_until(client, |msg| matches!(msg, ServerMessage::View(_))) {
+                ServerMessage::View(view) => assert!(view.character.is_some()),
+                _ => unreachable!(),
+            }
+        }
+        drop(clients);
+        let state = server.join().unwrap();
+        assert_eq!(state.players().len(), 3);
+    }
+
+    #[test]
+    fn invalid_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || run_game(&mut State::<String>::with_seed(Rules::default(), 0), &mut Server::new(listener)).unwrap());
+        let mut client = Client::connect(addr, "alice".to_owned()).unwrap();
+        recv_until(&mut client, |msg| matches!(msg, ServerMessage::Request(InputRequest::Meta)));
+        client.writer.write_all(b"{\"Input\"\n").unwrap();
+        recv_until(&mut client, |msg| matches!(msg, ServerMessage::Error(_)));
+        // the connection is still usable
+        client.send(Input::Meta(MetaInput::Join("alice".to_owned(), None))).unwrap();
+        recv_until(&mut client, |msg| matches!(msg, ServerMessage::View(view) if !view.players.is_empty()));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn duplicate_hello() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || run_game(&mut State::<String>::with_seed(Rules::default(), 0), &mut Server::new(listener)).unwrap());
+        let alice = Client::connect(addr, "alice".to_owned()).unwrap();
+        assert!(Client::connect(addr, "alice".to_owned()).is_err());
+        drop(alice);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn pass_for_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut state = State::<String>::with_seed(Rules::default(), 0);
+            for (seat, name) in ["alice", "bob", "carol"].iter().enumerate() {
+                state.advance_game(Input::Meta(MetaInput::Join(name.to_string(), Some(seat)))).unwrap();
+            }
+            state.advance_game(Input::Meta(MetaInput::Deal(vec![(0, Role::Seer, Identity::Romeo), (1, Role::Mayor, Identity::Juliet), (2, Role::Wolf, Identity::Single)]))).unwrap();
+            run_game(&mut state, &mut Server::new(listener)).unwrap();
+        });
+        let mut bob = Client::connect(addr, "bob".to_owned()).unwrap();
+        // the seer and the wolf leave without acting, so the game goes on without them
+        drop(Client::connect(addr, "alice".to_owned()).unwrap());
+        drop(Client::connect(addr, "carol".to_owned()).unwrap());
+        recv_until(&mut bob, |msg| matches!(msg, ServerMessage::Request(InputRequest::Action(Role::Mayor, _))));
+        drop(bob);
+        server.join().unwrap();
+    }
+}
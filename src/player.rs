@@ -8,6 +8,7 @@ use {
 };
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Player<P> {
     pub id: P,
     /// `None` = dead
@@ -28,6 +29,7 @@ impl<P> Player<P> {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Character {
     pub(crate) role: Role,
     pub(crate) identity: Identity,
@@ -50,6 +52,7 @@ impl Character {
 }
 
 #[derive(Debug, SmartDefault)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub(crate) enum Props {
     #[default]
     None,
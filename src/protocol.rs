@@ -0,0 +1,161 @@
+//! A line-delimited JSON protocol for playing from another process, over the network (see [`net`](crate::net)) or a child process's stdin/stdout (see [`external`](crate::external)).
+//!
+//! Each message is a JSON object on its own line. The player side starts each session with [`ClientMessage::Hello`], which the game side answers with [`ServerMessage::Welcome`]. The versions must match, otherwise the session is ended with [`ServerMessage::Error`]. Lines which aren't valid messages are answered with an error and otherwise ignored.
+//!
+//! After that, the game side sends the player's [`View`] and, if the player is asked for input, the [`InputRequest`]. The player side answers requests with [`ClientMessage::Input`]. In the wolf phase, each player only sends the votes of the wolves they act for, and the votes are combined once everyone has voted. Notifications and errors can arrive at any time.
+
+use {
+    std::{
+        collections::HashMap,
+        io::{
+            self,
+            prelude::*,
+        },
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+        de::DeserializeOwned,
+    },
+    crate::{
+        roles::Role,
+        state::{
+            Action,
+            Event,
+            IngameInput,
+            Input,
+            InputRequest,
+            MetaInput,
+            PlayerId,
+            Recipient,
+            Seat,
+            State,
+        },
+        view::View,
+    },
+};
+
+/// The version of the protocol described in this module. It changes whenever a message changes in an incompatible way.
+pub const VERSION: u32 = 1;
+
+/// Messages from the game to a player.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ServerMessage<P: PlayerId> {
+    /// Accepts the player's hello, telling them which protocol version is spoken and whom they play as.
+    Welcome {
+        version: u32,
+        player: P,
+    },
+    /// What the player is allowed to know, sent whenever the game waits for input.
+    View(View<P>),
+    /// The game is waiting for input from the player. Requests for meta inputs are sent to everyone, others only to the players listed in them.
+    Request(InputRequest<P>),
+    Notification(Recipient<P>, Event<P>),
+    /// The player's last message was rejected.
+    Error(String),
+}
+
+/// Messages from a player to the game.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ClientMessage<P: PlayerId> {
+    /// Starts the session, naming the protocol version spoken and the player.
+    Hello {
+        version: u32,
+        player: P,
+    },
+    Input(Input<P>),
+}
+
+/// Reads one message. Returns `None` at the end of the stream, and `Some(Err(_))` if the line isn't a valid message, in which case reading can go on with the next line.
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<serde_json::Result<T>>> {
+    let mut line = String::default();
+    if reader.read_line(&mut line)? == 0 { return Ok(None) }
+    Ok(Some(serde_json::from_str(&line)))
+}
+
+pub fn write_message(writer: &mut impl Write, msg: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, msg)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Checks that the player may give this input, so players can't act for others.
+pub(crate) fn check_sender<P: PlayerId>(state: &State<P>, ir: &InputRequest<P>, player: &P, input: Input<P>) -> Result<Input<P>, &'static str> {
+    let view = state.view_for(player);
+    let allowed = match (ir, &input) {
+        (_, Input::Meta(MetaInput::Join(p, _))) | (_, Input::Meta(MetaInput::Quit(p))) => p == player,
+        (_, Input::Meta(MetaInput::Go)) => view.seat.is_some(),
+        (_, Input::Meta(MetaInput::Deal(_))) => false,
+        (_, Input::Ingame(IngameInput::WinAttempt { claimant, .. })) => view.seat == Some(*claimant),
+        (InputRequest::Action(_, awaited), Input::Ingame(IngameInput::Action(action))) => awaited.contains(player) && match *action {
+            Action::Shoot(hunter) | Action::Aim(hunter, _) => view.acts_for(hunter),
+            // an empty vote would count as the sender's vote without them choosing a target
+            Action::Eat(ref votes) => !votes.is_empty() && votes.keys().all(|&wolf| view.acts_for(wolf)),
+            _ => true,
+        },
+        (InputRequest::Angel(_, _, awaited), Input::Ingame(IngameInput::AngelPass)) | (InputRequest::Angel(_, _, awaited), Input::Ingame(IngameInput::AngelVeto(_))) => awaited.contains(player),
+        (_, Input::Ingame(_)) => false,
+    };
+    if allowed { Ok(input) } else { Err("you can't give this input right now") }
+}
+
+/// Combines the votes the wolves send separately.
+#[derive(Debug)]
+pub(crate) struct Votes<P: PlayerId>(HashMap<P, HashMap<Seat, Seat>>);
+
+impl<P: PlayerId> Default for Votes<P> {
+    fn default() -> Votes<P> {
+        Votes(HashMap::default())
+    }
+}
+
+impl<P: PlayerId> Votes<P> {
+    /// Forgets all votes unless it's still the wolf phase.
+    pub(crate) fn reset(&mut self, ir: &InputRequest<P>) {
+        if !matches!(ir, InputRequest::Action(Role::Wolf, _)) { self.0.clear() }
+    }
+
+    /// Whether the player has already voted during this wolf phase.
+    pub(crate) fn contains(&self, player: &P) -> bool {
+        self.0.contains_key(player)
+    }
+
+    /// Records the player's votes. Returns the combined `Eat` action once all awaited players have voted.
+    pub(crate) fn add(&mut self, ir: &InputRequest<P>, player: P, votes: HashMap<Seat, Seat>) -> Option<Action> {
+        self.0.insert(player, votes);
+        let awaited = if let InputRequest::Action(_, awaited) = ir { awaited } else { return None };
+        if awaited.iter().all(|player| self.0.contains_key(player)) {
+            Some(Action::Eat(self.0.drain().flat_map(|(_, votes)| votes).collect()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            identities::Identity,
+            rules::Rules,
+        },
+        super::*,
+    };
+
+    #[test]
+    fn wolves_only_vote_for_themselves() {
+        let mut state = State::with_seed(Rules::default(), 0);
+        for (seat, name) in ["alice", "bob", "carol"].iter().enumerate() {
+            state.advance_game(Input::Meta(MetaInput::Join(name.to_string(), Some(seat)))).unwrap();
+        }
+        state.advance_game(Input::Meta(MetaInput::Deal(vec![(0, Role::Wolf, Identity::Romeo), (1, Role::Wolf, Identity::Juliet), (2, Role::Mayor, Identity::Single)]))).unwrap();
+        let ir = state.next_input();
+        let eat = |votes: &[(Seat, Seat)]| Input::Ingame(IngameInput::Action(Action::Eat(votes.iter().copied().collect())));
+        let alice = "alice".to_owned();
+        assert!(check_sender(&state, &ir, &alice, eat(&[(0, 2)])).is_ok());
+        assert!(check_sender(&state, &ir, &alice, eat(&[])).is_err());
+        assert!(check_sender(&state, &ir, &alice, eat(&[(1, 2)])).is_err());
+        assert!(check_sender(&state, &ir, &alice, eat(&[(0, 2), (1, 2)])).is_err());
+        assert!(check_sender(&state, &ir, &"carol".to_owned(), eat(&[(2, 0)])).is_err());
+    }
+}
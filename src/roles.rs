@@ -1,11 +1,14 @@
 use {
+    std::fmt,
     enum_iterator::IntoEnumIterator,
     smart_default::SmartDefault,
+    crate::rules::Rules,
     self::Role::*,
 };
 
 /// Roles of the game, which also are the phases of a day/night cycle
 #[derive(Debug, SmartDefault, Clone, Copy, IntoEnumIterator, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Role {
     /// first phase, not default role
     #[default]
@@ -20,9 +23,20 @@ pub enum Role {
     Angel,
 }
 
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 impl Role {
-    pub(crate) fn list() -> impl Iterator<Item = Role> {
+    pub(crate) fn list(rules: &Rules) -> impl Iterator<Item = Role> {
+        let hunters = rules.hunters;
         Role::into_enum_iter()
-            .flat_map(|role| if role == Wolf { vec![role; 3] } else { vec![role] })
+            .flat_map(move |role| match role {
+                Hunter => vec![role; hunters],
+                Wolf => vec![role; 3],
+                _ => vec![role],
+            })
     }
 }
@@ -0,0 +1,59 @@
+use smart_default::SmartDefault;
+
+/// Variations on the rules, chosen when setting up a game.
+#[derive(Debug, SmartDefault, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Rules {
+    /// what the seer learns about the player she inspects
+    pub inspection: Inspection,
+    /// what the jester swaps between two players
+    pub swap: SwapKind,
+    /// what happens when the wolves' votes are tied
+    pub eat_tie: Tie,
+    /// how many hunters are in the deck
+    #[default(1)]
+    pub hunters: usize,
+    /// how many points a player loses for a failed win attempt
+    #[default(1)]
+    pub win_attempt_penalty: u32,
+}
+
+/// What the seer learns about the player she inspects.
+#[derive(Debug, SmartDefault, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Inspection {
+    #[default]
+    Identity,
+    Role,
+}
+
+/// What the jester swaps between two players.
+#[derive(Debug, SmartDefault, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SwapKind {
+    /// Only the roles are swapped, along with everything that belongs to them (action tokens, bites, potions).
+    Roles,
+    /// Only the identities are swapped.
+    Identities,
+    /// The entire characters are swapped, and anything referring to either player now refers to the other.
+    #[default]
+    Both,
+}
+
+/// How a tie in the wolves' vote is broken.
+#[derive(Debug, SmartDefault, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Tie {
+    /// Nobody is eaten.
+    #[default]
+    NoKill,
+    /// One of the tied players is chosen at random.
+    Random,
+    /// The tied player with the lowest seat number is eaten.
+    LowestSeat,
+    /// The wolves have to vote again, up to [`MAX_REVOTES`] times. If their votes are still tied after that, nobody is eaten.
+    Revote,
+}
+
+/// How often the wolves vote again after a tie under [`Tie::Revote`].
+pub const MAX_REVOTES: usize = 2;
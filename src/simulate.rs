@@ -0,0 +1,347 @@
+//! The `simulate` subcommand: plays many games between built-in bots and reports statistics, to help balance the deck.
+
+use {
+    std::{
+        collections::HashMap,
+        io,
+    },
+    enum_iterator::IntoEnumIterator as _,
+    itertools::Itertools as _,
+    rand::prelude::*,
+    rand_pcg::Pcg64,
+    victory::{
+        bot::{
+            Bots,
+            Difficulty,
+        },
+        frontend::{
+            Frontend,
+            run_game,
+        },
+        identities::{
+            Identity,
+            Party,
+        },
+        roles::Role,
+        rules::Rules,
+        state::{
+            Event,
+            GameError,
+            Input,
+            InputRequest,
+            Position,
+            Recipient,
+            State,
+        },
+    },
+};
+
+/// Lets the bots play and watches all notifications, including private ones.
+struct Recorder {
+    bots: Bots<usize>,
+    /// the identity each player was dealt at the start of the segment
+    dealt: HashMap<usize, Identity>,
+    victors: Option<Vec<usize>>,
+    /// where in the segment the previous in-game request was made
+    last_position: Option<Position>,
+    cycles: usize,
+    skipped: HashMap<Role, usize>,
+}
+
+impl Recorder {
+    fn new(bots: Bots<usize>) -> Recorder {
+        Recorder {
+            bots,
+            dealt: HashMap::default(),
+            victors: None,
+            last_position: None,
+            cycles: 0,
+            skipped: HashMap::default(),
+        }
+    }
+
+    /// Counts the phases and cycles which passed since the previous request.
+    fn observe(&mut self, state: &State<usize>) {
+        if let Some(position) = state.position() {
+            for phase in position.skipped_since(self.last_position) {
+                *self.skipped.entry(phase).or_default() += 1;
+            }
+            self.cycles = position.cycle;
+            self.last_position = Some(position);
+        } else {
+            self.last_position = None;
+        }
+    }
+}
+
+impl Frontend<usize> for Recorder {
+    type Error = GameError;
+
+    fn give_input(&mut self, state: &State<usize>, ir: InputRequest<usize>) -> Result<Option<Input<usize>>, GameError> {
+        self.observe(state);
+        self.bots.give_input(state, ir)
+    }
+
+    fn notify(&mut self, recipient: Recipient<usize>, event: Event<usize>) -> Result<(), GameError> {
+        match (recipient, event) {
+            // players are told their character when it's dealt, and again after each swap
+            (Recipient::Player(player), Event::Character(_, identity)) => { self.dealt.entry(player).or_insert(identity); }
+            (_, Event::Victory(victors)) => self.victors = Some(victors),
+            (_, _) => {}
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, error: GameError) -> Result<(), GameError> {
+        self.bots.reject(error)
+    }
+}
+
+/// Totals over all simulated games.
+#[derive(Default)]
+struct Stats {
+    games: usize,
+    /// games stopped by the bots because the segment took too long
+    stuck: usize,
+    /// games which ended because nobody could act anymore, without a winner
+    no_winner: usize,
+    /// how often each identity was dealt, and how often it won
+    identities: HashMap<Identity, (usize, usize)>,
+    /// how often each party was dealt, and how often it won
+    parties: HashMap<Party, (usize, usize)>,
+    points: Vec<u32>,
+    /// the length of each game which wasn't stuck
+    cycles: Vec<usize>,
+    /// how often each phase was skipped in games which weren't stuck
+    skipped: HashMap<Role, usize>,
+}
+
+pub(crate) fn run(num_players: usize, games: usize, rules: Rules, difficulty: Difficulty, seed: u64) -> io::Result<()> {
+    let mut rng = Pcg64::seed_from_u64(seed);
+    let mut stats = Stats { points: vec![0; num_players], ..Stats::default() };
+    for _ in 0..games {
+        let game_seed = rng.gen();
+        let mut state = State::with_seed(rules.clone(), game_seed);
+        let mut recorder = Recorder::new(Bots::with_difficulty(0..num_players, difficulty, game_seed, 1));
+        run_game(&mut state, &mut recorder).map_err(io::Error::other)?;
+        if recorder.dealt.is_empty() { return Err(io::Error::new(io::ErrorKind::InvalidInput, "the deck doesn't have enough characters for this many players")) }
+        stats.record(&state, recorder);
+    }
+    stats.print();
+    Ok(())
+}
+
+impl Stats {
+    fn record(&mut self, state: &State<usize>, recorder: Recorder) {
+        self.games += 1;
+        let stuck = !matches!(state.next_input(), InputRequest::Meta);
+        if stuck {
+            self.stuck += 1;
+        } else if recorder.victors.is_none() {
+            self.no_winner += 1;
+        }
+        // players are credited with what they were dealt, even if their identity was swapped since
+        let won = |player| recorder.victors.as_ref().map_or(false, |victors| victors.contains(player));
+        for (player, &identity) in &recorder.dealt {
+            let (dealt, identity_won) = self.identities.entry(identity).or_default();
+            *dealt += 1;
+            if won(player) { *identity_won += 1 }
+        }
+        for party in recorder.dealt.values().map(Identity::party).unique() {
+            let (dealt, party_won) = self.parties.entry(party).or_default();
+            *dealt += 1;
+            if recorder.dealt.iter().any(|(player, identity)| identity.party() == party && won(player)) { *party_won += 1 }
+        }
+        for (seat, player) in state.players().iter().enumerate() {
+            self.points[seat] += state.points().get(&player.id).copied().unwrap_or_default();
+        }
+        // stuck games go on until the bots give up, which would skew the length and skipped phases
+        if stuck { return }
+        // the cycle in which the segment ended counts as well
+        self.cycles.push(recorder.cycles + 1);
+        for (role, count) in recorder.skipped {
+            *self.skipped.entry(role).or_default() += count;
+        }
+    }
+
+    fn print(&self) {
+        let percent = |count: usize, total: usize| if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 };
+        println!("{} games, {} stopped because they took too long, {} ended without a winner", self.games, self.stuck, self.no_winner);
+        println!();
+        println!("{:<16} {:>6} {:>6} {:>9}", "identity", "dealt", "won", "win rate");
+        for identity in Identity::into_enum_iter() {
+            let (dealt, won) = self.identities.get(&identity).copied().unwrap_or_default();
+            println!("{:<16} {:>6} {:>6} {:>8.1}%", identity.to_string(), dealt, won, percent(won, dealt));
+        }
+        println!();
+        println!("{:<16} {:>6} {:>6} {:>9}", "party", "dealt", "won", "win rate");
+        for party in Identity::into_enum_iter().map(|identity| identity.party()).unique() {
+            let (dealt, won) = self.parties.get(&party).copied().unwrap_or_default();
+            println!("{:<16} {:>6} {:>6} {:>8.1}%", party.to_string(), dealt, won, percent(won, dealt));
+        }
+        println!();
+        println!("{:<6} {:>10}", "seat", "avg points");
+        for (seat, &points) in self.points.iter().enumerate() {
+            println!("{:<6} {:>10.2}", seat, points as f64 / self.games.max(1) as f64);
+        }
+        println!();
+        let total_cycles = self.cycles.iter().sum::<usize>();
+        println!(
+            "game length in cycles, for the {} games which ended: {:.2} on average, {} to {}",
+            self.cycles.len(),
+            total_cycles as f64 / self.cycles.len().max(1) as f64,
+            self.cycles.iter().min().copied().unwrap_or_default(),
+            self.cycles.iter().max().copied().unwrap_or_default(),
+        );
+        println!();
+        println!("{:<8} {:>8} {:>10}", "phase", "skipped", "of cycles");
+        for role in Role::into_enum_iter().filter(|&role| role != Role::Angel) {
+            let skipped = self.skipped.get(&role).copied().unwrap_or_default();
+            println!("{:<8} {:>8} {:>9.1}%", role.to_string(), skipped, percent(skipped, total_cycles));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        victory::{
+            bot::Difficulty,
+            state::{
+                Action,
+                IngameInput,
+                MetaInput,
+            },
+        },
+        super::*,
+    };
+
+    /// A game with three players at the start of a segment, or between segments if nothing is dealt.
+    fn game(deal: &[(Role, Identity)]) -> State<usize> {
+        game_with(Rules::default(), deal)
+    }
+
+    fn game_with(rules: Rules, deal: &[(Role, Identity)]) -> State<usize> {
+        let mut state = State::with_seed(rules, 0);
+        for seat in 0..3 { state.advance_game(Input::Meta(MetaInput::Join(seat, Some(seat)))).unwrap(); }
+        if !deal.is_empty() {
+            state.advance_game(Input::Meta(MetaInput::Deal(deal.iter().enumerate().map(|(seat, &(role, identity))| (seat, role, identity)).collect()))).unwrap();
+        }
+        state
+    }
+
+    fn recorder(dealt: &[Identity], victors: Option<Vec<usize>>) -> Recorder {
+        Recorder {
+            dealt: dealt.iter().copied().enumerate().collect(),
+            victors,
+            cycles: 2,
+            ..Recorder::new(Bots::with_difficulty(0..3, Difficulty::Normal, 0, 1))
+        }
+    }
+
+    fn stats() -> Stats {
+        Stats { points: vec![0; 3], ..Stats::default() }
+    }
+
+    #[test]
+    fn record_win() {
+        let mut stats = stats();
+        stats.record(&game(&[]), recorder(&[Identity::Romeo, Identity::Mozart, Identity::Single], Some(vec![0, 1])));
+        assert_eq!((stats.games, stats.stuck, stats.no_winner), (1, 0, 0));
+        assert_eq!(stats.identities[&Identity::Romeo], (1, 1));
+        assert_eq!(stats.identities[&Identity::Mozart], (1, 1));
+        assert_eq!(stats.identities[&Identity::Single], (1, 0));
+        assert_eq!(stats.parties[&Party::Lovers], (1, 1));
+        assert_eq!(stats.parties[&Party::Masons], (1, 1));
+        assert_eq!(stats.cycles, vec![3]);
+    }
+
+    #[test]
+    fn record_stuck() {
+        let mut stats = stats();
+        let state = game(&[(Role::Seer, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        stats.record(&state, recorder(&[Identity::Romeo, Identity::Juliet, Identity::Single], None));
+        assert_eq!((stats.games, stats.stuck, stats.no_winner), (1, 1, 0));
+        assert_eq!(stats.identities[&Identity::Romeo], (1, 0));
+        assert_eq!(stats.parties[&Party::Lovers], (1, 0));
+        assert!(stats.cycles.is_empty());
+    }
+
+    #[test]
+    fn record_no_winner() {
+        let mut stats = stats();
+        stats.record(&game(&[]), recorder(&[Identity::Romeo, Identity::Juliet, Identity::Churchill], None));
+        assert_eq!((stats.games, stats.stuck, stats.no_winner), (1, 0, 1));
+        assert_eq!(stats.identities[&Identity::Churchill], (1, 0));
+        assert_eq!(stats.parties[&Party::Masons], (1, 0));
+        assert_eq!(stats.cycles, vec![3]);
+    }
+
+    /// Passes the inputs to the game one by one, observing the state before each of them like [`Recorder::give_input`] does.
+    fn observe_game(state: &mut State<usize>, inputs: Vec<IngameInput>) -> Recorder {
+        let mut recorder = Recorder::new(Bots::with_difficulty(0..3, Difficulty::Normal, 0, 1));
+        for input in inputs {
+            recorder.observe(state);
+            state.advance_game(Input::Ingame(input)).unwrap();
+        }
+        recorder.observe(state);
+        recorder
+    }
+
+    fn skipped(recorder: &Recorder) -> Vec<(Role, usize)> {
+        Role::into_enum_iter().filter_map(|role| recorder.skipped.get(&role).map(|&count| (role, count))).collect()
+    }
+
+    #[test]
+    fn single_hunter_cycle() {
+        let mut state = game(&[(Role::Hunter, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        let recorder = observe_game(&mut state, vec![
+            IngameInput::Action(Action::Aim(0, 2)),
+            IngameInput::Action(Action::Inspect(0)),
+            IngameInput::Action(Action::VoteResult(None)),
+        ]);
+        assert_eq!(state.position().map(|position| position.phase), Some(Role::Hunter));
+        assert_eq!(recorder.cycles, 1);
+        assert_eq!(skipped(&recorder), vec![(Role::Vampire, 1), (Role::Jester, 1), (Role::Wolf, 1), (Role::Witch, 1)]);
+    }
+
+    #[test]
+    fn multi_hunter_cycle() {
+        let mut state = game_with(Rules { hunters: 2, ..Rules::default() }, &[(Role::Hunter, Identity::Romeo), (Role::Hunter, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        // the hunter phase is requested again after the first hunter has aimed
+        let recorder = observe_game(&mut state, vec![
+            IngameInput::Action(Action::Aim(0, 2)),
+            IngameInput::Action(Action::Aim(1, 2)),
+            IngameInput::Action(Action::VoteResult(None)),
+        ]);
+        assert_eq!(state.position().map(|position| position.phase), Some(Role::Hunter));
+        assert_eq!(recorder.cycles, 1);
+        assert_eq!(skipped(&recorder), vec![(Role::Vampire, 1), (Role::Jester, 1), (Role::Seer, 1), (Role::Wolf, 1), (Role::Witch, 1)]);
+    }
+
+    #[test]
+    fn angel_veto() {
+        let mut state = game(&[(Role::Seer, Identity::Romeo), (Role::Angel, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        // the phase is requested again for the angel after each action
+        let recorder = observe_game(&mut state, vec![
+            IngameInput::Action(Action::Inspect(2)),
+            IngameInput::AngelVeto(0),
+            IngameInput::Action(Action::VoteResult(None)),
+            IngameInput::AngelPass,
+        ]);
+        assert_eq!(state.position().map(|position| position.phase), Some(Role::Seer));
+        assert_eq!(recorder.cycles, 1);
+        assert_eq!(skipped(&recorder), vec![(Role::Hunter, 2), (Role::Vampire, 2), (Role::Jester, 2), (Role::Wolf, 1), (Role::Witch, 1)]);
+    }
+
+    #[test]
+    fn rejected_input() {
+        let mut state = game(&[(Role::Hunter, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        let mut recorder = observe_game(&mut state, vec![IngameInput::Action(Action::Aim(0, 2))]);
+        assert!(state.advance_game(Input::Ingame(IngameInput::Action(Action::Shoot(0)))).is_err());
+        recorder.observe(&state);
+        assert_eq!(state.position().map(|position| position.phase), Some(Role::Seer));
+        assert_eq!(recorder.cycles, 0);
+        assert_eq!(skipped(&recorder), vec![(Role::Vampire, 1), (Role::Jester, 1)]);
+    }
+}
@@ -4,29 +4,34 @@ use {
             HashMap,
             HashSet,
         },
+        error::Error,
+        fmt,
         hash::Hash,
+        iter,
         mem,
     },
     enum_iterator::IntoEnumIterator as _,
     hashbag::HashBag,
-    itertools::{
-        EitherOrBoth,
-        Itertools as _,
-    },
-    multiset::HashMultiSet,
+    itertools::Itertools as _,
     rand::prelude::*,
-    smart_default::SmartDefault,
+    rand_pcg::Pcg64,
     crate::{
-        identities::{
-            Identity,
-            Wincon,
-        },
+        identities::Identity,
+        log::Log,
         roles::Role,
+        rules::{
+            Inspection,
+            MAX_REVOTES,
+            Rules,
+            SwapKind,
+            Tie,
+        },
         util::Cycle as _,
         player::{
             Character,
             Player,
-        }
+            Props,
+        },
     },
 };
 
@@ -34,54 +39,221 @@ pub trait PlayerId: Eq + Hash + Clone {}
 
 impl<T: Eq + Hash + Clone> PlayerId for T {}
 
-#[derive(Debug, SmartDefault)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct State<P: PlayerId> {
+    rules: Rules,
+    /// the seed `rng` was created from
+    seed: u64,
+    /// used for all random decisions, such as seat choice, dealing, and breaking ties
+    rng: Pcg64,
+    /// all inputs applied so far, for replaying the game
+    history: Vec<Input<P>>,
     points: HashMap<P, u32>,
     pub(crate) players: Vec<Player<P>>,
     just_quit: HashSet<P>,
     /// `None` means between segments (i.e. players can join/leave)
     pub(crate) phase: Option<Role>,
-    /// whether somebody died this round (relevant for queen)
-    pub(crate) mourning: bool,
-    /// the target chosen by the hunter
-    target: Option<Seat>, // TODO multiple hunters
+    /// the roles and identities of everyone who died this cycle (relevant for the solo identities' win conditions)
+    pub(crate) fallen: Vec<(Role, Identity)>,
+    /// the targets chosen by the hunters, keyed by the hunter's seat, kept until the hunter or the target dies or the segment ends
+    pub(crate) targets: HashMap<Seat, Seat>,
+    /// the player eaten by the wolves, who dies after the witch phase unless healed
+    pub(crate) dying: Option<Seat>,
+    /// how often the wolves have voted again after a tie during this cycle
+    revotes: usize,
+    /// how many cycles have been completed during this segment
+    cycle: usize,
     /// the person shielded from being vetoed _again_ by the angel
-    shielded: Option<Seat>,
+    pub(crate) shielded: Option<Seat>,
     /// `true` if the angel shield is from this cycle, `false` if it's from last
     shield_is_current: bool,
-    /// the action pending approval by the angel (within frame set by front-end)
-    pending_action: Option<Action>,
+    /// the action pending approval by the angel (within frame set by front-end), along with the seats of the players taking it
+    pub(crate) pending_action: Option<(Vec<Seat>, Action)>,
+    /// what each player has learned privately during this segment
+    insights: HashMap<P, Vec<Insight<P>>>,
+    /// notifications caused by the input currently being applied, returned by `advance_game`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    notifications: Vec<(Recipient<P>, Event<P>)>,
+}
+
+impl<P: PlayerId> Default for State<P> {
+    fn default() -> State<P> {
+        State::new(Rules::default())
+    }
 }
 
 impl<P: PlayerId> State<P> {
+    pub fn new(rules: Rules) -> State<P> {
+        State::with_seed(rules, thread_rng().gen())
+    }
+
+    /// Creates a game whose random decisions are all determined by the given seed.
+    pub fn with_seed(rules: Rules, seed: u64) -> State<P> {
+        State {
+            rules, seed,
+            rng: Pcg64::seed_from_u64(seed),
+            history: Vec::default(),
+            points: HashMap::default(),
+            players: Vec::default(),
+            just_quit: HashSet::default(),
+            phase: None,
+            fallen: Vec::default(),
+            targets: HashMap::default(),
+            dying: None,
+            revotes: 0,
+            cycle: 0,
+            shielded: None,
+            shield_is_current: false,
+            pending_action: None,
+            insights: HashMap::default(),
+            notifications: Vec::default(),
+        }
+    }
+
+    pub fn rules(&self) -> &Rules { &self.rules }
+    /// The seed this game's random decisions are derived from.
+    pub fn seed(&self) -> u64 { self.seed }
     pub fn points(&self) -> &HashMap<P, u32> { &self.points }
     pub fn players(&self) -> &[Player<P>] { &self.players }
     pub fn just_quit(&self) -> &HashSet<P> { &self.just_quit }
 
-    pub fn next_input(&self) -> InputRequest {
+    /// Returns a log of this game so far, which can be used to [replay](Log::replay) it.
+    pub fn log(&self) -> Log<P> {
+        Log {
+            rules: self.rules.clone(),
+            seed: self.seed,
+            inputs: self.history.clone(),
+        }
+    }
+
+    /// Returns what the given player has learned privately during this segment, e.g. from seer inspections.
+    pub fn insights(&self, player: &P) -> &[Insight<P>] {
+        self.insights.get(player).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns how far the current segment has progressed, or `None` between segments.
+    pub fn position(&self) -> Option<Position> {
+        self.phase.map(|phase| Position { cycle: self.cycle, phase })
+    }
+
+    pub fn next_input(&self) -> InputRequest<P> {
         if let Some(phase) = self.phase {
-            if let Some(ref p_a) = self.pending_action {
-                InputRequest::Angel(phase, p_a)
+            if let Some((_, ref p_a)) = self.pending_action {
+                let angels = self.living_players_with_role(Role::Angel).map(|seat| self.actor_seat(seat)).collect_vec();
+                InputRequest::Angel(phase, p_a.clone(), self.awaited_players(angels))
             } else {
-                InputRequest::Action(phase)
+                InputRequest::Action(phase, self.awaited_players(self.current_actor_seats()))
             }
         } else {
             InputRequest::Meta
         }
     }
 
-    /// # Panics
+    /// Returns every input which would currently be accepted, so frontends can offer them as choices.
+    ///
+    /// Joins aren't included since they need a new player's id (see [`legal_inputs_for`](State::legal_inputs_for)), and neither are deals or win attempts, of which there are too many. Win attempts are accepted at any time during a segment anyway, and only penalized if false. The wolves' votes are listed as one `Eat` for each combination of targets for all wolves who can act.
+    pub fn legal_inputs(&self) -> Vec<Input<P>> {
+        if self.phase.is_some() { return self.legal_ingame_inputs(|_| true) }
+        let mut inputs = self.players.iter().map(|player| Input::Meta(MetaInput::Quit(player.id.clone()))).collect_vec();
+        if self.enough_characters() { inputs.push(Input::Meta(MetaInput::Go)) }
+        inputs
+    }
+
+    /// Returns the inputs the given player may give right now: joining at any seat if they're not in the game, otherwise quitting or starting the segment, and during a segment, the inputs from [`legal_inputs`](State::legal_inputs) they're awaited for.
+    ///
+    /// During the wolf phase, the listed votes only include the wolves this player acts for. They're only accepted together with the other wolves' votes.
+    pub fn legal_inputs_for(&self, player: &P) -> Vec<Input<P>> {
+        let seat = self.players.iter().position(|iter_player| iter_player.id == *player);
+        if self.phase.is_some() { return seat.map_or_else(Vec::default, |seat| self.legal_ingame_inputs(|actor| actor == seat)) }
+        if seat.is_some() {
+            let mut inputs = vec![Input::Meta(MetaInput::Quit(player.clone()))];
+            if self.enough_characters() { inputs.push(Input::Meta(MetaInput::Go)) }
+            inputs
+        } else if self.just_quit.contains(player) {
+            Vec::default()
+        } else {
+            iter::once(None).chain((0..=self.players.len()).map(Some)).map(|join_seat| Input::Meta(MetaInput::Join(player.clone(), join_seat))).collect()
+        }
+    }
+
+    /// The valid in-game inputs which are taken by actors for whom `acts` returns `true`.
+    fn legal_ingame_inputs(&self, acts: impl Fn(Seat) -> bool) -> Vec<Input<P>> {
+        let phase = self.phase.expect("can't list in-game inputs in between segments");
+        if let Some((ref actor_seats, _)) = self.pending_action {
+            if !self.living_players_with_role(Role::Angel).map(|seat| self.actor_seat(seat)).any(|seat| acts(seat) && self.players[seat].can_act()) { return Vec::default() }
+            return iter::once(IngameInput::AngelPass)
+                .chain(actor_seats.iter().copied().unique().filter(|&seat| self.shielded != Some(seat)).map(IngameInput::AngelVeto))
+                .map(Input::Ingame)
+                .collect()
+        }
+        let living = (0..self.players.len()).filter(|&seat| self.players[seat].character.is_some()).collect_vec();
+        let actions = match phase {
+            Role::Hunter => self.living_players_with_role(Role::Hunter)
+                .flat_map(|hunter| iter::once(Action::Shoot(hunter)).chain(living.iter().map(move |&target| Action::Aim(hunter, target))))
+                .collect_vec(),
+            Role::Vampire => living.iter().map(|&seat| Action::Bite(seat)).collect(),
+            Role::Jester => living.iter().tuple_combinations().map(|(&seat0, &seat1)| Action::Swap(seat0, seat1)).collect(),
+            Role::Seer => living.iter().map(|&seat| Action::Inspect(seat)).collect(),
+            Role::Wolf => {
+                let wolves = self.living_players_with_role(Role::Wolf).filter(|&wolf| self.players[self.actor_seat(wolf)].can_act()).collect_vec();
+                if wolves.is_empty() { return Vec::default() }
+                // only the complete vote is accepted, so the votes of the wolves someone acts for are listed if they're part of a valid complete vote
+                return wolves.iter()
+                    .map(|_| living.iter().copied())
+                    .multi_cartesian_product()
+                    .map(|targets| wolves.iter().copied().zip(targets).collect::<HashMap<_, _>>())
+                    .filter(|votes| self.validate_action(phase, &Action::Eat(votes.clone())).is_ok())
+                    .map(|votes| votes.into_iter().filter(|&(wolf, _)| acts(self.actor_seat(wolf))).sorted().collect_vec())
+                    .filter(|votes| !votes.is_empty())
+                    .unique()
+                    .map(|votes| Input::Ingame(IngameInput::Action(Action::Eat(votes.into_iter().collect()))))
+                    .collect()
+            }
+            Role::Witch => iter::once(None).chain(living.iter().copied().map(Some))
+                .flat_map(|poison| vec![Action::Potions { heal: false, poison }, Action::Potions { heal: true, poison }])
+                .collect(),
+            Role::Mayor => iter::once(None).chain(living.iter().copied().map(Some)).map(Action::VoteResult).collect(),
+            Role::Angel => Vec::default(),
+        };
+        actions.into_iter()
+            .filter(|action| self.validate_action(phase, action).map_or(false, |actor_seats| actor_seats.into_iter().all(&acts)))
+            .map(|action| Input::Ingame(IngameInput::Action(action)))
+            .collect()
+    }
+
+    /// Returns the ids of the players in the given seats who can currently act, without duplicates.
+    fn awaited_players(&self, seats: Vec<Seat>) -> Vec<P> {
+        seats.into_iter()
+            .unique()
+            .filter(|&seat| self.players[seat].can_act())
+            .map(|seat| self.players[seat].id.clone())
+            .collect()
+    }
+
+    /// # Errors
     ///
-    /// Panics on invalid inputs (e.g. `AngelVeto` with a player ID that doesn't have a pending action).
-    pub fn advance_game(&mut self, input: Input<P>) {
+    /// Returns an error on invalid inputs (e.g. `AngelVeto` when there is no pending action). The state is left unchanged in that case.
+    ///
+    /// On success, returns the notifications the input caused, in order, along with who may see them.
+    pub fn advance_game(&mut self, input: Input<P>) -> Result<Vec<(Recipient<P>, Event<P>)>, GameError> {
+        self.apply(input.clone())?;
+        self.history.push(input);
+        Ok(mem::take(&mut self.notifications))
+    }
+
+    fn apply(&mut self, input: Input<P>) -> Result<(), GameError> {
         match input {
             Input::Meta(meta_input) => {
-                assert!(self.phase.is_none());
+                if self.phase.is_some() { return Err(GameError::MetaInputIngame) }
                 match meta_input {
                     MetaInput::Join(p, join_seat) => {
-                        assert!(!self.players.iter().any(|player| player.id == p));
-                        assert!(!self.just_quit.iter().any(|player_id| *player_id == p));
-                        let join_seat = join_seat.unwrap_or_else(|| thread_rng().gen_range(0, self.players.len().max(1)));
+                        if self.players.iter().any(|player| player.id == p) { return Err(GameError::AlreadyJoined) }
+                        if self.just_quit.contains(&p) { return Err(GameError::JustQuit) }
+                        if let Some(join_seat) = join_seat {
+                            if join_seat > self.players.len() { return Err(GameError::NoSuchSeat(join_seat)) }
+                        }
+                        let join_seat = join_seat.unwrap_or_else(|| self.rng.gen_range(0, self.players.len().max(1)));
                         self.players.insert(join_seat, Player::new(p));
                         for player in &mut self.players {
                             if let Some(vampire_seat) = player.character.as_mut().and_then(|c| c.bitten_by.as_mut()) {
@@ -90,8 +262,9 @@ impl<P: PlayerId> State<P> {
                         }
                     }
                     MetaInput::Quit(p) => {
-                        let (quit_seat, _) = self.players.iter().enumerate().find(|(_, player)| player.id == p).expect("player is already not in the game");
+                        let quit_seat = self.players.iter().position(|player| player.id == p).ok_or(GameError::NotInGame)?;
                         self.players.retain(|player| player.id != p);
+                        self.insights.remove(&p);
                         self.just_quit.insert(p);
                         self.cleanup_player_references(quit_seat);
                         for player in &mut self.players {
@@ -101,64 +274,181 @@ impl<P: PlayerId> State<P> {
                         }
                     }
                     MetaInput::Go => {
-                        fn free_attributes<P, T: Copy + Eq + Hash>(players: &[Player<P>], list: impl Iterator<Item = T>, player_attr: impl Fn(&Character) -> T) -> Vec<T> {
-                            let attrs = list.collect::<HashMultiSet<_>>()
-                                - players.iter()
-                                    .filter_map(|player| player.character.as_ref())
-                                    .map(player_attr)
-                                    .collect();
-                            let mut attrs = attrs.iter().copied().collect_vec();
-                            attrs.shuffle(&mut thread_rng());
-                            attrs
+                        if !self.enough_characters() { return Err(GameError::NotEnoughCharacters) }
+                        let mut free_roles = self.free_attributes(Role::list(&self.rules), |c| c.role);
+                        let mut free_identities = self.free_attributes(Identity::into_enum_iter(), |c| c.identity);
+                        free_roles.shuffle(&mut self.rng);
+                        free_identities.shuffle(&mut self.rng);
+                        let dead_seats = (0..self.players.len()).filter(|&seat| self.players[seat].character.is_none()).collect_vec();
+                        self.start_segment(dead_seats.into_iter().zip(free_roles.into_iter().zip(free_identities)).map(|(seat, (role, identity))| (seat, role, identity)).collect());
+                    }
+                    MetaInput::Deal(characters) => {
+                        let mut free_roles = self.free_attributes(Role::list(&self.rules), |c| c.role);
+                        let mut free_identities = self.free_attributes(Identity::into_enum_iter(), |c| c.identity);
+                        let mut dealt_seats = HashSet::new();
+                        for &(seat, role, identity) in &characters {
+                            if seat >= self.players.len() { return Err(GameError::NoSuchSeat(seat)) }
+                            if self.players[seat].character.is_some() || !dealt_seats.insert(seat) { return Err(GameError::AlreadyDealt(seat)) }
+                            free_roles.remove(free_roles.iter().position(|&free_role| free_role == role).ok_or(GameError::UnavailableRole(role))?);
+                            free_identities.remove(free_identities.iter().position(|&free_identity| free_identity == identity).ok_or(GameError::UnavailableIdentity(identity))?);
                         }
-                        
-                        self.just_quit.clear();
-                        let free_roles = free_attributes(&self.players, Role::list(), |c| c.role);
-                        let free_identities = free_attributes(&self.players, Identity::into_enum_iter(), |c| c.identity);
-                        self.players.iter_mut()
-                            .filter(|player| player.character.is_none())
-                            .zip_longest(free_roles.into_iter().zip(free_identities))
-                            .for_each(|pair| match pair {
-                                EitherOrBoth::Left(_) => panic!("not enough roles and/or identities"),
-                                EitherOrBoth::Right(_) => {}
-                                EitherOrBoth::Both(dead_player, (free_role, free_identity)) => dead_player.character = Some(Character::new(free_role, free_identity)),
-                            });
-                        self.phase = Some(Role::default());
+                        if let Some(seat) = (0..self.players.len()).find(|&seat| self.players[seat].character.is_none() && !dealt_seats.contains(&seat)) { return Err(GameError::NotDealt(seat)) }
+                        self.start_segment(characters);
                     }
                 }
             }
             Input::Ingame(ingame_input) => {
-                assert!(self.phase.is_some());
+                let phase = self.phase.ok_or(GameError::BetweenSegments)?;
                 match ingame_input {
                     IngameInput::Action(action) => {
-                        assert!(self.pending_action.is_none());
-                        let actor_seats = if let Action::Eat(ref votes) = action { votes.keys().copied().collect() } else { self.current_actor_seats() };
-                        assert!(actor_seats.iter().all(|&seat| self.players[seat].can_act()));
+                        if self.pending_action.is_some() { return Err(GameError::ActionPending) }
+                        let actor_seats = self.validate_action(phase, &action)?;
                         actor_seats.iter().for_each(|&seat| if let Some(ref mut c) = self.players[seat].character { c.ready = false });
-                        if self.living_players_with_role(Role::Angel).next().is_some()
+                        // angels whose vampire has already used their token can't respond, so the action isn't held for them
+                        if self.living_players_with_role(Role::Angel).any(|seat| self.players[self.actor_seat(seat)].can_act())
                         && actor_seats.iter().any(|&seat| self.shielded.map_or(true, |shielded| seat != shielded)) {
-                            self.pending_action = Some(action);
+                            self.pending_action = Some((actor_seats, action));
                         } else {
                             self.resolve_action(actor_seats, action);
                         }
                     }
                     IngameInput::AngelPass => {
-                        let actor_seats = self.current_actor_seats();
-                        let pending = self.pending_action.take().expect("no pending action to pass");
+                        let (actor_seats, pending) = self.pending_action.take().ok_or(GameError::NoPendingAction)?;
                         self.resolve_action(actor_seats, pending);
-                        self.pending_action = None;
                     }
                     IngameInput::AngelVeto(seat) => {
-                        //TODO validate seat?
+                        let (ref actor_seats, _) = self.pending_action.as_ref().ok_or(GameError::NoPendingAction)?;
+                        if !actor_seats.contains(&seat) { return Err(GameError::NotAnActor(seat)) }
+                        if self.shielded == Some(seat) { return Err(GameError::Shielded(seat)) }
                         self.pending_action = None; //TODO Werwölfe?
                         self.shielded = Some(seat);
                         self.shield_is_current = true;
-                        self.set_next_phase();
+                        self.finish_action();
                     },
-                    IngameInput::WinAttempt() => unimplemented!() //TODO
+                    IngameInput::WinAttempt { claimant, identity, partners } => {
+                        self.check_alive(claimant)?;
+                        if let Some(&seat) = partners.iter().find(|&&seat| seat >= self.players.len()) { return Err(GameError::NoSuchSeat(seat)) }
+                        let actual_identity = self.players[claimant].character.as_ref().expect("check_alive passed for a dead player").identity;
+                        if identity == actual_identity && identity.wincon().is_flipped(self, claimant, &partners) {
+                            self.victory(iter::once(claimant).chain(partners).collect());
+                        } else {
+                            let claimant_id = self.players[claimant].id.clone();
+                            let points = self.points.entry(claimant_id.clone()).or_default();
+                            let lost = (*points).min(self.rules.win_attempt_penalty);
+                            *points -= lost;
+                            self.notify(Recipient::Everyone, Event::FalseClaim(claimant_id.clone()));
+                            self.notify(Recipient::Player(claimant_id), Event::PointsLost(lost));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether enough roles and identities are left to deal one of each to everyone without a character.
+    fn enough_characters(&self) -> bool {
+        let dead = self.players.iter().filter(|player| player.character.is_none()).count();
+        dead <= self.free_attributes(Role::list(&self.rules), |c| c.role).len().min(self.free_attributes(Identity::into_enum_iter(), |c| c.identity).len())
+    }
+
+    /// Returns the roles or identities from the given list which are not held by any living player.
+    fn free_attributes<T: Copy + Eq>(&self, list: impl Iterator<Item = T>, player_attr: impl Fn(&Character) -> T) -> Vec<T> {
+        let mut attrs = list.collect_vec();
+        for taken in self.players.iter().filter_map(|player| player.character.as_ref()).map(player_attr) {
+            if let Some(idx) = attrs.iter().position(|&attr| attr == taken) { attrs.remove(idx); }
+        }
+        attrs
+    }
+
+    /// Gives the characters to the players in the given seats and starts the first phase in which anyone can act.
+    fn start_segment(&mut self, characters: Vec<(Seat, Role, Identity)>) {
+        self.just_quit.clear();
+        for (seat, role, identity) in characters {
+            self.players[seat].character = Some(Character::new(role, identity));
+            self.notify_seat(seat, Event::Character(role, identity));
+        }
+        // survivors of the previous segment may have used their token in the cycle it ended in
+        for c in self.players.iter_mut().filter_map(|player| player.character.as_mut()) { c.ready = true }
+        self.phase = Some(Role::default());
+        if !self.has_choices(Role::default()) { self.set_next_phase() }
+    }
+
+    /// Checks that the action may be taken in the current phase and returns the seats of the players taking it.
+    fn validate_action(&self, phase: Role, action: &Action) -> Result<Vec<Seat>, GameError> {
+        if action.role() != phase { return Err(GameError::WrongPhase(phase)) }
+        match *action {
+            Action::VoteResult(None) => {}
+            Action::VoteResult(Some(seat)) | Action::Bite(seat) | Action::Inspect(seat) => self.check_alive(seat)?,
+            Action::Shoot(hunter) => {
+                self.check_role(hunter, Role::Hunter)?;
+                if !self.targets.contains_key(&hunter) { return Err(GameError::NoTarget) }
+            }
+            Action::Aim(hunter, target) => {
+                self.check_role(hunter, Role::Hunter)?;
+                self.check_alive(target)?;
+            }
+            Action::Swap(seat0, seat1) => {
+                self.check_alive(seat0)?;
+                self.check_alive(seat1)?;
+            }
+            Action::Eat(ref votes) => for (&wolf, &target) in votes {
+                self.check_role(wolf, Role::Wolf)?;
+                self.check_alive(target)?;
+            },
+            Action::Potions { heal, poison } => {
+                let (health_potion, poison_potion) = self.witch_potions()?;
+                if heal {
+                    if !health_potion { return Err(GameError::NoPotion) }
+                    if self.dying.is_none() { return Err(GameError::NobodyDying) }
+                }
+                if let Some(seat) = poison {
+                    if !poison_potion { return Err(GameError::NoPotion) }
+                    self.check_alive(seat)?;
                 }
             }
         }
+        let actor_seats = match *action {
+            Action::Eat(ref votes) => votes.keys().map(|&wolf| self.actor_seat(wolf)).collect(),
+            Action::Shoot(hunter) | Action::Aim(hunter, _) => vec![self.actor_seat(hunter)],
+            _ => self.current_actor_seats(),
+        };
+        if let Some(&seat) = actor_seats.iter().find(|&&seat| !self.players[seat].can_act()) { return Err(GameError::CannotAct(seat)) }
+        if let Action::Eat(ref votes) = *action {
+            // every wolf who can act has to vote
+            if let Some(wolf) = self.living_players_with_role(Role::Wolf).find(|&wolf| self.players[self.actor_seat(wolf)].can_act() && !votes.contains_key(&wolf)) { return Err(GameError::MissingVote(wolf)) }
+            if votes.is_empty() { return Err(GameError::AmbiguousActor) }
+        }
+        if let Action::Bite(_) | Action::Inspect(_) = *action {
+            if actor_seats.len() != 1 { return Err(GameError::AmbiguousActor) }
+        }
+        Ok(actor_seats)
+    }
+
+    /// Returns the seat of the living witch.
+    fn witch_seat(&self) -> Result<Seat, GameError> {
+        self.living_players_with_role(Role::Witch).exactly_one().map_err(|_| GameError::AmbiguousActor)
+    }
+
+    /// Returns which potions the living witch has left, as `(health_potion, poison_potion)`.
+    fn witch_potions(&self) -> Result<(bool, bool), GameError> {
+        let witch_seat = self.witch_seat()?;
+        Ok(match self.players[witch_seat].character.as_ref().expect("witch_seat yielded a dead player").props {
+            Props::Witch { health_potion, poison_potion } => (health_potion, poison_potion),
+            Props::None => (false, false),
+        })
+    }
+
+    fn check_role(&self, seat: Seat, role: Role) -> Result<(), GameError> {
+        self.check_alive(seat)?;
+        if self.players[seat].character.as_ref().expect("check_alive passed for a dead player").role == role { Ok(()) } else { Err(GameError::WrongRole(seat)) }
+    }
+
+    fn check_alive(&self, seat: Seat) -> Result<(), GameError> {
+        match self.players.get(seat) {
+            Some(player) => if player.character.is_some() { Ok(()) } else { Err(GameError::DeadPlayer(seat)) },
+            None => Err(GameError::NoSuchSeat(seat)),
+        }
     }
 
     fn living_players_with_role(&self, role: Role) -> impl Iterator<Item = Seat> + '_ {
@@ -168,65 +458,171 @@ impl<P: PlayerId> State<P> {
             .map(|(seat, _)| seat)
     }
 
+    /// Returns the seat of the player who acts for the living player in the given seat, i.e. their vampire if they have been bitten.
+    fn actor_seat(&self, seat: Seat) -> Seat {
+        self.players[seat].character.as_ref().and_then(|c| c.bitten_by).unwrap_or(seat)
+    }
+
     fn current_actor_seats(&self) -> Vec<Seat> {
         let phase = self.phase.expect("can't use actions in between segments");
         self.living_players_with_role(phase)
-            .map(|seat| if let Some(vampire_seat) = self.players[seat].character.as_ref().expect("living_players_with_role yielded a dead player").bitten_by { vampire_seat } else { seat })
+            .map(|seat| self.actor_seat(seat))
             .collect()
     }
 
     fn resolve_action(&mut self, actor_seats: Vec<Seat>, action: Action) {
         match action {
-            Action::Shoot => self.kill(self.target.expect("no target to shoot")),
-            Action::Aim(seat) => {
-                assert!(self.players[seat].character.is_some()); // can't aim at dead players
-                self.target = Some(seat);
-            }
+            Action::Shoot(hunter) => self.kill(self.targets[&hunter]),
+            Action::Aim(hunter, target) => { self.targets.insert(hunter, target); }
             Action::Bite(seat) => {
-                if let Some(ref mut c) = self.players[seat].character {
-                    c.bitten_by = Some(actor_seats.into_iter().exactly_one().expect("unclear who bit you"));
-                } else {
-                    panic!("can't bite dead player")
-                }
+                let vampire_seat = actor_seats.into_iter().exactly_one().expect("unclear who bit you");
+                self.players[seat].character.as_mut().expect("can't bite dead player").bitten_by = Some(vampire_seat);
+                self.notify_seat(seat, Event::Bitten);
+            }
+            Action::Inspect(seat) => {
+                let seer_seat = actor_seats.into_iter().exactly_one().expect("unclear who inspected");
+                let c = self.players[seat].character.as_ref().expect("can't inspect dead player");
+                let insight = match self.rules.inspection {
+                    Inspection::Identity => Insight::Identity(self.players[seat].id.clone(), c.identity),
+                    Inspection::Role => Insight::Role(self.players[seat].id.clone(), c.role),
+                };
+                self.insights.entry(self.players[seer_seat].id.clone()).or_default().push(insight.clone());
+                self.notify_seat(seer_seat, Event::Insight(insight));
             }
             Action::Swap(seat0, seat1) => {
-                //TODO
+                self.swap(seat0, seat1);
+                for &seat in &[seat0, seat1] {
+                    let c = self.players[seat].character.as_ref().expect("can't swap dead player");
+                    let event = Event::Character(c.role, c.identity);
+                    self.notify_seat(seat, event);
+                }
             }
-            Action::Eat(map) => {
-                let counts = map.into_iter().map(|(_, target)| target).collect::<HashBag<_>>();
-                if let Some((seat, _)) = counts.into_iter().max_by_key(|(_, count)| *count) {
-                    self.kill(seat);
+            Action::Eat(votes) => {
+                let counts = votes.into_iter().map(|(_, target)| target).collect::<HashBag<_>>().into_iter().collect_vec();
+                let max_count = counts.iter().map(|&(_, count)| count).max();
+                let mut tied = counts.into_iter().filter(|&(_, count)| Some(count) == max_count).map(|(seat, _)| seat).collect_vec();
+                tied.sort();
+                self.dying = if tied.len() > 1 {
+                    match self.rules.eat_tie {
+                        Tie::Random => tied.choose(&mut self.rng).copied(),
+                        Tie::LowestSeat => tied.first().copied(),
+                        Tie::Revote if self.revotes < MAX_REVOTES => {
+                            self.revotes += 1;
+                            actor_seats.iter().for_each(|&seat| if let Some(ref mut c) = self.players[seat].character { c.ready = true });
+                            return
+                        }
+                        Tie::NoKill | Tie::Revote => None,
+                    }
+                } else {
+                    tied.first().copied()
+                };
+            }
+            Action::Potions { heal, poison } => {
+                let witch_seat = self.witch_seat().expect("no witch to use potions");
+                if let Props::Witch { ref mut health_potion, ref mut poison_potion } = self.players[witch_seat].character.as_mut().expect("witch_seat yielded a dead player").props {
+                    if heal { *health_potion = false }
+                    if poison.is_some() { *poison_potion = false }
                 }
-            },
+                if heal { self.dying = None }
+                if let Some(seat) = poison { self.kill(seat) }
+            }
             Action::VoteResult(opt_p) => if let Some(p) = opt_p { self.kill(p) },
         }
-        self.set_next_phase();
+        self.finish_action();
+    }
+
+    /// Moves on to the next phase, unless there are hunters who haven't acted yet.
+    fn finish_action(&mut self) {
+        if self.phase == Some(Role::Hunter) && self.has_choices(Role::Hunter) {
+            self.check_wincons();
+        } else {
+            self.set_next_phase();
+        }
+    }
+
+    /// Swaps the roles and/or identities (depending on the rules) of two living players.
+    fn swap(&mut self, seat0: Seat, seat1: Seat) {
+        fn swap_seat(seat: &mut Seat, seat0: Seat, seat1: Seat) {
+            if *seat == seat0 { *seat = seat1 } else if *seat == seat1 { *seat = seat0 }
+        }
+
+        if seat0 == seat1 { return }
+        let (lower, higher) = self.players.split_at_mut(seat0.max(seat1));
+        let c0 = lower[seat0.min(seat1)].character.as_mut().expect("can't swap dead player");
+        let c1 = higher[0].character.as_mut().expect("can't swap dead player");
+        match self.rules.swap {
+            SwapKind::Roles => {
+                mem::swap(&mut c0.role, &mut c1.role);
+                mem::swap(&mut c0.ready, &mut c1.ready);
+                mem::swap(&mut c0.bitten_by, &mut c1.bitten_by);
+                mem::swap(&mut c0.props, &mut c1.props);
+            }
+            SwapKind::Identities => mem::swap(&mut c0.identity, &mut c1.identity),
+            SwapKind::Both => mem::swap(c0, c1),
+        }
+        if self.rules.swap != SwapKind::Identities {
+            // bites and aims stay with the vampire and hunter roles
+            for c in self.players.iter_mut().filter_map(|player| player.character.as_mut()) {
+                if let Some(ref mut vampire_seat) = c.bitten_by { swap_seat(vampire_seat, seat0, seat1) }
+            }
+            self.targets = self.targets.drain().map(|(mut hunter, target)| {
+                swap_seat(&mut hunter, seat0, seat1);
+                (hunter, target)
+            }).collect();
+        }
+        if self.rules.swap == SwapKind::Both {
+            for seat in self.targets.values_mut().chain(&mut self.shielded).chain(&mut self.dying) { swap_seat(seat, seat0, seat1) }
+        }
     }
 
     fn set_next_phase(&mut self) {
         let mut phase = self.phase.expect("can't advance phase in between segments");
+        let mut cleanups = 0;
         loop {
             phase = phase.succ();
+            self.phase = Some(phase);
+            if phase.prev() == Role::Witch {
+                if let Some(seat) = self.dying.take() { self.kill(seat) }
+            }
             self.check_wincons();
+            if self.phase.is_none() { break } // somebody won, segment is over
             if phase == Role::Angel {
+                cleanups += 1;
+                if cleanups > 1 {
+                    // nobody was able to act for an entire cycle
+                    self.end_segment();
+                    break
+                }
                 // angel phase used for end-of-cycle cleanup
                 if self.shield_is_current {
                     self.shield_is_current = false;
                 } else {
                     self.shielded = None;
                 }
-                self.mourning = false;
-                self.target = None;
-            } else if !self.living_players_with_role(phase).any(|seat| self.players[seat].can_act() /*TODO and has choices to make */) { break }
+                self.fallen.clear();
+                self.revotes = 0;
+                self.cycle += 1;
+                for c in self.players.iter_mut().filter_map(|player| player.character.as_mut()) { c.ready = true }
+            } else if self.has_choices(phase) { break }
         }
-        self.phase = Some(phase);
+    }
+
+    /// Whether any player has a decision to make in the given phase.
+    fn has_choices(&self, phase: Role) -> bool {
+        self.living_players_with_role(phase).any(|seat| {
+            let c = self.players[seat].character.as_ref().expect("living_players_with_role yielded a dead player");
+            self.players[c.bitten_by.unwrap_or(seat)].can_act() && match c.props {
+                Props::Witch { health_potion, poison_potion } => health_potion && self.dying.is_some() || poison_potion,
+                Props::None => true,
+            }
+        })
     }
 
     fn check_wincons(&mut self) {
         let vs = self.players.iter()
             .enumerate()
             .filter(|(_, player)| player.character.as_ref().map_or(false,
-                |c| if let Wincon::Static(check) = c.identity.wincon() { check(self) } else { false }
+                |c| c.identity.wincon().holds(self)
             ))
             .map(|(seat, _)| seat)
             .collect_vec();
@@ -234,10 +630,11 @@ impl<P: PlayerId> State<P> {
     }
 
     fn kill(&mut self, seat: Seat) { //TODO day/night? method?
-        assert!(self.players[seat].character.is_some()); // make sure player was alive
-        self.players[seat].character = None;
-        self.mourning = true;
-        if self.target == Some(seat) { self.target = None } //TODO move to cleanup_player_references? (depends on how kicking players/handling disconnects work)
+        let c = self.players[seat].character.take().expect("player was already dead");
+        self.fallen.push((c.role, c.identity));
+        self.notify(Recipient::Everyone, Event::Died(self.players[seat].id.clone()));
+        if self.dying == Some(seat) { self.dying = None }
+        self.targets.retain(|&hunter, &mut target| hunter != seat && target != seat); //TODO move to cleanup_player_references? (depends on how kicking players/handling disconnects work)
         self.cleanup_player_references(seat);
     }
 
@@ -255,16 +652,40 @@ impl<P: PlayerId> State<P> {
     fn victory(&mut self, mut victors: Vec<Seat>) {
         let with_victors = self.players.iter()
             .enumerate()
-            .filter(|(_, player)| player.character.as_ref().map_or(false, |c| c.identity.wincon().is_with(self, &victors)))
+            .filter(|&(seat, player)| !victors.contains(&seat) && player.character.as_ref().map_or(false, |c| c.identity.wincon().is_with(self, &victors)))
             .map(|(seat, _)| seat)
             .collect_vec();
         victors.extend(with_victors);
-        let State { mut points, mut players, .. } = mem::replace(self, State::default());
-        victors.iter().for_each(|&seat| players[seat].character = None); // “kill” all victors so they get a new role/identity and count for the number of points
-        let gain = players.iter().filter(|player| player.character.is_none()).count() as u32;
-        victors.iter().for_each(|&seat| *points.entry(players[seat].id.clone()).or_default() += gain);
+        victors.iter().for_each(|&seat| self.players[seat].character = None); // “kill” all victors so they get a new role/identity and count for the number of points
+        let gain = self.players.iter().filter(|player| player.character.is_none()).count() as u32;
+        let victor_ids = victors.iter().map(|&seat| self.players[seat].id.clone()).collect_vec();
+        victor_ids.iter().for_each(|p| *self.points.entry(p.clone()).or_default() += gain);
+        self.end_segment();
+        self.notify(Recipient::Everyone, Event::Victory(victor_ids.clone()));
+        for p in victor_ids {
+            self.notify(Recipient::Player(p), Event::PointsWon(gain));
+        }
+    }
+
+    /// Goes back to between segments, e.g. so new players can join.
+    fn end_segment(&mut self) {
+        // everything else only lasts for one segment
+        let State { rules, seed, rng, history, points, players, notifications, .. } = mem::replace(self, State::with_seed(Rules::default(), 0));
+        self.rules = rules;
+        self.seed = seed;
+        self.rng = rng;
+        self.history = history;
         self.points = points;
         self.players = players;
+        self.notifications = notifications;
+    }
+
+    fn notify(&mut self, recipient: Recipient<P>, event: Event<P>) {
+        self.notifications.push((recipient, event));
+    }
+
+    fn notify_seat(&mut self, seat: Seat, event: Event<P>) {
+        self.notify(Recipient::Player(self.players[seat].id.clone()), event);
     }
 
     pub(crate) fn player_by_id(&self, pid: &P) -> &Player<P> {
@@ -272,40 +693,962 @@ impl<P: PlayerId> State<P> {
     }
 }
 
-pub enum InputRequest<'a> {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum InputRequest<P: PlayerId> {
     /// Accepting all [`Input::Meta`].
     Meta,
-    /// Accepting actions or win attempts.
-    Action(Role),
-    /// Accepting angel vetos, angel passes, or win attempts.
-    Angel(Role, &'a Action),
+    /// Accepting actions or win attempts. The players listed are the ones who act in this phase, including vampires acting for the players they bit.
+    Action(Role, Vec<P>),
+    /// Accepting angel vetos, angel passes, or win attempts. The players listed are the ones who decide on the pending action.
+    Angel(Role, Action, Vec<P>),
+}
+
+/// How far a segment has progressed, see [`State::position`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Position {
+    /// how many cycles have been completed during the segment
+    pub cycle: usize,
+    pub phase: Role,
+}
+
+impl Position {
+    /// Returns the phases in which nobody could act since the given earlier position in the same segment, or since the start of the segment if `None`. The angel phase isn't included, since it's only used for the cleanup at the end of each cycle unless there's a pending action.
+    ///
+    /// Frontends can compare the positions of consecutive input requests this way, e.g. so a narrator can call skipped phases anyway.
+    pub fn skipped_since(self, last: Option<Position>) -> Vec<Role> {
+        let phases = Role::into_enum_iter().collect_vec();
+        let idx = |position: Position| position.cycle * phases.len() + phases.iter().position(|&phase| phase == position.phase).expect("phase not in phase order");
+        let start = last.map_or(0, |last| idx(last) + 1);
+        (start..idx(self)).map(|idx| phases[idx % phases.len()]).filter(|&phase| phase != Role::Angel).collect()
+    }
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Input<P: Eq + Hash> {
     Meta(MetaInput<P>),
     Ingame(IngameInput),
 }
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum MetaInput<P: Eq + Hash> {
     Join(P, Option<Seat>),
     Quit(P),
+    /// Deals random roles and identities to everyone without a character and starts the segment.
     Go,
+    /// Like `Go`, but with the given role and identity for each player without a character, e.g. because the game is played with physical cards.
+    Deal(Vec<(Seat, Role, Identity)>),
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum IngameInput {
     Action(Action),
     AngelPass,
     AngelVeto(Seat),
-    WinAttempt(),
+    /// A player reveals their identity and names their partners, claiming to have won.
+    ///
+    /// Win attempts are accepted during any phase. If the claim is false, the claimant loses points as set in the rules.
+    WinAttempt {
+        claimant: Seat,
+        identity: Identity,
+        partners: Vec<Seat>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Action {
     VoteResult(Option<Seat>), //individual votes are front-end issue
-    Shoot,
-    Aim(Seat),
+    /// The hunter in the given seat shoots their target.
+    Shoot(Seat),
+    /// The hunter in the first seat aims at the player in the second seat.
+    Aim(Seat, Seat),
     Bite(Seat),
     Swap(Seat, Seat),
+    /// The seer learns the identity or role (depending on the rules) of a living player.
+    Inspect(Seat),
+    /// The wolves' votes, mapping each wolf's seat to the seat of the player they want to eat.
     Eat(HashMap<Seat, Seat>),
+    /// The witch may heal the player eaten by the wolves and/or poison a living player. Using neither potion passes.
+    Potions {
+        heal: bool,
+        poison: Option<Seat>,
+    },
+}
+
+impl Action {
+    /// The role (and thus phase) this action belongs to.
+    pub fn role(&self) -> Role {
+        match self {
+            Action::VoteResult(_) => Role::Mayor,
+            Action::Shoot(_) | Action::Aim(_, _) => Role::Hunter,
+            Action::Bite(_) => Role::Vampire,
+            Action::Swap(_, _) => Role::Jester,
+            Action::Inspect(_) => Role::Seer,
+            Action::Eat(_) => Role::Wolf,
+            Action::Potions { .. } => Role::Witch,
+        }
+    }
 }
 
+/// Something a player has learned privately, only shown to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Insight<P> {
+    /// A seer inspection revealed this player's identity.
+    Identity(P, Identity),
+    /// A seer inspection revealed this player's role.
+    Role(P, Role),
+}
+
+/// Who may see a notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Recipient<P> {
+    /// The notification is public.
+    Everyone,
+    /// The notification is private to this player.
+    Player(P),
+}
+
+/// Something that happened in the game, as returned by [`State::advance_game`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Event<P> {
+    /// The recipient has been dealt this role and identity, or has received it in a swap.
+    Character(Role, Identity),
+    /// The recipient has been bitten by the vampire.
+    Bitten,
+    /// The recipient has learned something from a seer inspection.
+    Insight(Insight<P>),
+    /// This player has died.
+    Died(P),
+    /// These players have won the segment.
+    Victory(Vec<P>),
+    /// The recipient has won this many points.
+    PointsWon(u32),
+    /// This player has made a false win attempt.
+    FalseClaim(P),
+    /// The recipient has lost this many points for a false win attempt.
+    PointsLost(u32),
+}
+
+/// The reasons why [`State::advance_game`] can reject an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum GameError {
+    /// A meta input was given during a segment.
+    MetaInputIngame,
+    /// An in-game input was given between segments.
+    BetweenSegments,
+    /// The joining player is already in the game.
+    AlreadyJoined,
+    /// The joining player has just quit and can only rejoin after the next segment has started.
+    JustQuit,
+    /// The quitting player is not in the game.
+    NotInGame,
+    /// There are not enough roles and/or identities left for all players without a character.
+    NotEnoughCharacters,
+    /// There is no player in this seat.
+    NoSuchSeat(Seat),
+    /// The player in this seat is dead.
+    DeadPlayer(Seat),
+    /// The action doesn't belong to the current phase, which is given.
+    WrongPhase(Role),
+    /// The player in this seat can't act right now, e.g. because they have been bitten.
+    CannotAct(Seat),
+    /// It is unclear which player is taking the action.
+    AmbiguousActor,
+    /// The player in this seat doesn't have the role required for the action.
+    WrongRole(Seat),
+    /// The wolf in this seat can vote but didn't.
+    MissingVote(Seat),
+    /// The hunter tried to shoot without having a target.
+    NoTarget,
+    /// The witch tried to use a potion she has already used.
+    NoPotion,
+    /// The witch tried to heal while nobody has been eaten by the wolves.
+    NobodyDying,
+    /// An action was given while another action is pending approval by the angel.
+    ActionPending,
+    /// An angel pass or veto was given while no action is pending.
+    NoPendingAction,
+    /// The angel tried to veto a player who isn't taking the pending action.
+    NotAnActor(Seat),
+    /// The angel tried to veto a player who is shielded from being vetoed again.
+    Shielded(Seat),
+    /// A character was dealt to the player in this seat, who already has one.
+    AlreadyDealt(Seat),
+    /// No character was dealt to the player in this seat, who needs one.
+    NotDealt(Seat),
+    /// This role was dealt, but it isn't in the deck or already held by another player.
+    UnavailableRole(Role),
+    /// This identity was dealt, but it is already held by another player.
+    UnavailableIdentity(Identity),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::MetaInputIngame => write!(f, "players can only join, leave, or start the game between segments"),
+            GameError::BetweenSegments => write!(f, "the game hasn't started yet"),
+            GameError::AlreadyJoined => write!(f, "this player is already in the game"),
+            GameError::JustQuit => write!(f, "this player has just quit and can rejoin once the next segment has started"),
+            GameError::NotInGame => write!(f, "this player is not in the game"),
+            GameError::NotEnoughCharacters => write!(f, "not enough roles and/or identities for all players"),
+            GameError::NoSuchSeat(seat) => write!(f, "there is no seat {}", seat),
+            GameError::DeadPlayer(seat) => write!(f, "the player in seat {} is dead", seat),
+            GameError::WrongPhase(phase) => write!(f, "this action can't be taken during the {:?} phase", phase),
+            GameError::CannotAct(seat) => write!(f, "the player in seat {} can't act right now", seat),
+            GameError::AmbiguousActor => write!(f, "it is unclear who is taking this action"),
+            GameError::WrongRole(seat) => write!(f, "the player in seat {} doesn't have the role for this action", seat),
+            GameError::MissingVote(seat) => write!(f, "the wolf in seat {} hasn't voted", seat),
+            GameError::NoTarget => write!(f, "there is no target to shoot"),
+            GameError::NoPotion => write!(f, "the witch has already used this potion"),
+            GameError::NobodyDying => write!(f, "nobody has been eaten by the wolves"),
+            GameError::ActionPending => write!(f, "an action is already waiting for the angel"),
+            GameError::NoPendingAction => write!(f, "there is no action waiting for the angel"),
+            GameError::NotAnActor(seat) => write!(f, "the player in seat {} isn't taking the pending action", seat),
+            GameError::Shielded(seat) => write!(f, "the player in seat {} can't be vetoed again", seat),
+            GameError::AlreadyDealt(seat) => write!(f, "the player in seat {} already has a character", seat),
+            GameError::NotDealt(seat) => write!(f, "the player in seat {} needs a character", seat),
+            GameError::UnavailableRole(role) => write!(f, "there is no {} left to deal", role),
+            GameError::UnavailableIdentity(identity) => write!(f, "there is no {} left to deal", identity),
+        }
+    }
+}
+
+impl Error for GameError {}
+
 pub(crate) type Seat = usize;
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::identities::SoloIdentity,
+        super::*,
+    };
+
+    /// A game in the given phase where the player IDs are the seat numbers.
+    fn game(rules: Rules, phase: Role, characters: &[(Role, Identity)]) -> State<usize> {
+        let mut state = State::new(rules);
+        state.players = characters.iter().enumerate().map(|(seat, &(role, identity))| Player {
+            id: seat,
+            character: Some(Character::new(role, identity)),
+        }).collect();
+        state.phase = Some(phase);
+        state
+    }
+
+    /// A game in the jester phase where everyone is the queen, with a seer to stop the phase from advancing too far.
+    fn jester_phase(swap: SwapKind, roles: &[Role]) -> State<usize> {
+        let characters = roles.iter().map(|&role| (role, Identity::Solo(SoloIdentity::Queen))).collect_vec();
+        game(Rules { swap, ..Rules::default() }, Role::Jester, &characters)
+    }
+
+    fn character(state: &State<usize>, seat: Seat) -> &Character {
+        state.players[seat].character.as_ref().expect("player is dead")
+    }
+
+    #[test]
+    fn swap_both() {
+        let mut state = jester_phase(SwapKind::Both, &[Role::Jester, Role::Witch, Role::Vampire, Role::Seer, Role::Hunter]);
+        if let Props::Witch { ref mut health_potion, .. } = state.players[1].character.as_mut().unwrap().props { *health_potion = false }
+        state.players[3].character.as_mut().unwrap().bitten_by = Some(2);
+        state.targets.insert(4, 1);
+        state.shielded = Some(2);
+        state.advance_game(Input::Ingame(IngameInput::Action(Action::Swap(1, 2)))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+        assert_eq!(character(&state, 1).role, Role::Vampire);
+        assert_eq!(character(&state, 2).role, Role::Witch);
+        if let Props::Witch { health_potion, poison_potion } = character(&state, 2).props {
+            assert!(!health_potion);
+            assert!(poison_potion);
+        } else {
+            panic!("potions didn't follow the witch")
+        }
+        assert_eq!(character(&state, 3).bitten_by, Some(1));
+        assert_eq!(state.targets[&4], 2);
+        assert_eq!(state.shielded, Some(1));
+    }
+
+    #[test]
+    fn swap_roles() {
+        let mut state = jester_phase(SwapKind::Roles, &[Role::Jester, Role::Vampire, Role::Witch, Role::Seer, Role::Hunter]);
+        state.players[2].character.as_mut().unwrap().bitten_by = Some(1);
+        state.players[3].character.as_mut().unwrap().bitten_by = Some(1);
+        state.targets.insert(4, 2);
+        state.shielded = Some(1);
+        state.advance_game(Input::Ingame(IngameInput::Action(Action::Swap(1, 2)))).unwrap();
+        assert_eq!(character(&state, 1).role, Role::Witch);
+        assert_eq!(character(&state, 1).bitten_by, Some(2));
+        if let Props::None = character(&state, 1).props { panic!("potions didn't follow the witch") }
+        assert_eq!(character(&state, 2).role, Role::Vampire);
+        assert_eq!(character(&state, 2).bitten_by, None);
+        if let Props::Witch { .. } = character(&state, 2).props { panic!("potions stayed with the player") }
+        assert_eq!(character(&state, 3).bitten_by, Some(2));
+        assert_eq!(state.targets[&4], 2);
+        assert_eq!(state.shielded, Some(1));
+    }
+
+    #[test]
+    fn swap_hunter_role() {
+        let mut state = jester_phase(SwapKind::Roles, &[Role::Jester, Role::Hunter, Role::Witch, Role::Seer]);
+        state.targets.insert(1, 2);
+        state.advance_game(Input::Ingame(IngameInput::Action(Action::Swap(1, 2)))).unwrap();
+        assert_eq!(character(&state, 2).role, Role::Hunter);
+        assert_eq!(state.targets.get(&1), None);
+        assert_eq!(state.targets[&2], 2);
+    }
+
+    #[test]
+    fn swap_identities() {
+        let mut state = jester_phase(SwapKind::Identities, &[Role::Jester, Role::Witch, Role::Vampire, Role::Seer]);
+        state.players[1].character.as_mut().unwrap().identity = Identity::Romeo;
+        state.players[3].character.as_mut().unwrap().bitten_by = Some(2);
+        state.targets.insert(0, 1);
+        state.swap(1, 2);
+        assert_eq!(character(&state, 1).role, Role::Witch);
+        assert_eq!(character(&state, 1).identity, Identity::Solo(SoloIdentity::Queen));
+        assert_eq!(character(&state, 2).role, Role::Vampire);
+        assert_eq!(character(&state, 2).identity, Identity::Romeo);
+        assert_eq!(character(&state, 3).bitten_by, Some(2));
+        assert_eq!(state.targets[&0], 1);
+    }
+
+    #[test]
+    fn swap_dead_player() {
+        let mut state = jester_phase(SwapKind::Both, &[Role::Jester, Role::Witch, Role::Seer]);
+        state.players[1].character = None;
+        assert_eq!(state.advance_game(Input::Ingame(IngameInput::Action(Action::Swap(1, 2)))), Err(GameError::DeadPlayer(1)));
+        assert_eq!(state.phase, Some(Role::Jester));
+    }
+
+    fn win_attempt(claimant: Seat, identity: Identity, partners: Vec<Seat>) -> Input<usize> {
+        Input::Ingame(IngameInput::WinAttempt { claimant, identity, partners })
+    }
+
+    #[test]
+    fn lovers_flip() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Romeo), (Role::Witch, Identity::Churchill), (Role::Seer, Identity::Single)]);
+        state.points.insert(0, 2);
+        for attempt in vec![win_attempt(0, Identity::Romeo, vec![1]), win_attempt(0, Identity::Romeo, vec![0]), win_attempt(0, Identity::Romeo, vec![1, 2]), win_attempt(0, Identity::Juliet, vec![2])] {
+            state.advance_game(attempt).unwrap();
+            assert_eq!(state.phase, Some(Role::Hunter));
+        }
+        assert_eq!(state.points.get(&0), Some(&0));
+        assert_eq!(state.advance_game(win_attempt(0, Identity::Romeo, vec![3])), Err(GameError::NoSuchSeat(3)));
+        state.advance_game(win_attempt(0, Identity::Romeo, vec![2])).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&2));
+        assert_eq!(state.points.get(&1), None);
+        assert_eq!(state.points.get(&2), Some(&2));
+    }
+
+    fn action(action: Action) -> Input<usize> {
+        Input::Ingame(IngameInput::Action(action))
+    }
+
+    #[test]
+    fn queen_wins_without_deaths() {
+        let mut state = game(Rules::default(), Role::Mayor, &[(Role::Mayor, Identity::Solo(SoloIdentity::Queen)), (Role::Witch, Identity::Churchill), (Role::Seer, Identity::Romeo)]);
+        state.advance_game(action(Action::VoteResult(None))).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&2));
+        assert_eq!(state.points.get(&1), Some(&2));
+        assert_eq!(state.points.get(&2), None);
+    }
+
+    #[test]
+    fn queen_loses_after_death() {
+        let mut state = game(Rules::default(), Role::Mayor, &[(Role::Mayor, Identity::Solo(SoloIdentity::Queen)), (Role::Witch, Identity::Churchill), (Role::Seer, Identity::Romeo)]);
+        state.advance_game(action(Action::VoteResult(Some(1)))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+        assert!(state.points.is_empty());
+    }
+
+    #[test]
+    fn sherlock_wins_when_jack_dies() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Solo(SoloIdentity::Sherlock)), (Role::Witch, Identity::Solo(SoloIdentity::JackTheRipper)), (Role::Seer, Identity::Doyle), (Role::Mayor, Identity::Romeo)]);
+        state.targets.insert(0, 1);
+        state.advance_game(action(Action::Shoot(0))).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&3));
+        assert_eq!(state.points.get(&2), Some(&3));
+        assert_eq!(state.points.get(&3), None);
+    }
+
+    #[test]
+    fn v_wins_when_queen_dies() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Solo(SoloIdentity::V)), (Role::Witch, Identity::Solo(SoloIdentity::Queen)), (Role::Seer, Identity::Churchill)]);
+        state.targets.insert(0, 1);
+        state.advance_game(action(Action::Shoot(0))).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&2));
+        assert_eq!(state.points.get(&2), None);
+    }
+
+    #[test]
+    fn jack_wins_after_two_deaths() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Solo(SoloIdentity::JackTheRipper)), (Role::Witch, Identity::Romeo), (Role::Mayor, Identity::Juliet), (Role::Seer, Identity::Single)]);
+        state.targets.insert(0, 1);
+        state.advance_game(action(Action::Shoot(0))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+        state.advance_game(action(Action::Inspect(0))).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        state.advance_game(action(Action::VoteResult(Some(2)))).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&3));
+    }
+
+    #[test]
+    fn macbeth_wins_when_mayor_dies() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Solo(SoloIdentity::Macbeth)), (Role::Mayor, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        state.targets.insert(0, 1);
+        state.advance_game(action(Action::Shoot(0))).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&2));
+    }
+
+    #[test]
+    fn multiple_hunters() {
+        let mut state = game(Rules { hunters: 2, ..Rules::default() }, Role::Hunter, &[(Role::Hunter, Identity::Romeo), (Role::Hunter, Identity::Juliet), (Role::Seer, Identity::Single), (Role::Mayor, Identity::Doyle)]);
+        state.targets.insert(1, 3);
+        assert_eq!(state.advance_game(action(Action::Aim(2, 3))), Err(GameError::WrongRole(2)));
+        state.advance_game(action(Action::Aim(0, 2))).unwrap();
+        assert_eq!(state.phase, Some(Role::Hunter));
+        assert_eq!(state.advance_game(action(Action::Aim(0, 3))), Err(GameError::CannotAct(0)));
+        state.advance_game(action(Action::Shoot(1))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+        assert!(state.players[3].character.is_none());
+        assert_eq!(state.targets.len(), 1);
+        assert_eq!(state.targets[&0], 2);
+        state.advance_game(action(Action::Inspect(0))).unwrap();
+        assert_eq!(state.phase, Some(Role::Hunter));
+        assert_eq!(state.targets[&0], 2);
+    }
+
+    #[test]
+    fn aim_then_shoot_next_cycle() {
+        let mut state = lobby(4);
+        state.advance_game(Input::Meta(MetaInput::Deal(vec![(0, Role::Hunter, Identity::Romeo), (1, Role::Seer, Identity::Juliet), (2, Role::Mayor, Identity::Single), (3, Role::Wolf, Identity::Doyle)]))).unwrap();
+        assert_eq!(state.phase, Some(Role::Hunter));
+        state.advance_game(action(Action::Aim(0, 3))).unwrap();
+        assert_rejected(&mut state, action(Action::Shoot(0)), GameError::WrongPhase(Role::Seer));
+        state.advance_game(action(Action::Inspect(3))).unwrap();
+        state.advance_game(action(Action::Eat(vec![(3, 2)].into_iter().collect()))).unwrap();
+        assert_eq!(state.phase, Some(Role::Hunter));
+        assert_eq!(state.view_for(&0).character.unwrap().target, Some(3));
+        state.advance_game(action(Action::Shoot(0))).unwrap();
+        assert!(state.players[3].character.is_none());
+        assert!(state.targets.is_empty());
+    }
+
+    #[test]
+    fn target_cleared_when_hunter_dies() {
+        let mut state = lobby(4);
+        state.advance_game(Input::Meta(MetaInput::Deal(vec![(0, Role::Hunter, Identity::Romeo), (1, Role::Seer, Identity::Juliet), (2, Role::Mayor, Identity::Single), (3, Role::Wolf, Identity::Doyle)]))).unwrap();
+        state.advance_game(action(Action::Aim(0, 3))).unwrap();
+        state.advance_game(action(Action::Inspect(3))).unwrap();
+        state.advance_game(action(Action::Eat(vec![(3, 0)].into_iter().collect()))).unwrap();
+        assert!(state.players[0].character.is_none());
+        assert!(state.targets.is_empty());
+    }
+
+    fn eat(tie: Tie, votes: &[(Seat, Seat)]) -> State<usize> {
+        let mut state = game(Rules { eat_tie: tie, ..Rules::default() }, Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single), (Role::Seer, Identity::Doyle), (Role::Hunter, Identity::Mozart)]);
+        state.advance_game(action(Action::Eat(votes.iter().copied().collect()))).unwrap();
+        state
+    }
+
+    #[test]
+    fn eat_majority() {
+        let state = eat(Tie::NoKill, &[(0, 3), (1, 3)]);
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert!(state.players[3].character.is_none());
+    }
+
+    #[test]
+    fn eat_ties() {
+        let state = eat(Tie::NoKill, &[(0, 3), (1, 4)]);
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert!(state.players.iter().all(|player| player.character.is_some()));
+        let state = eat(Tie::LowestSeat, &[(0, 4), (1, 3)]);
+        assert!(state.players[3].character.is_none());
+        assert!(state.players[4].character.is_some());
+        let state = eat(Tie::Random, &[(0, 3), (1, 4)]);
+        assert_eq!(state.players.iter().filter(|player| player.character.is_none()).count(), 1);
+        let mut state = eat(Tie::Revote, &[(0, 3), (1, 4)]);
+        assert_eq!(state.phase, Some(Role::Wolf));
+        state.advance_game(action(Action::Eat(vec![(0, 4), (1, 4)].into_iter().collect()))).unwrap();
+        assert!(state.players[4].character.is_none());
+    }
+
+    #[test]
+    fn eat_validation() {
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single), (Role::Seer, Identity::Doyle)]);
+        state.players[1].character.as_mut().unwrap().ready = false;
+        assert_eq!(state.advance_game(action(Action::Eat(vec![(2, 3)].into_iter().collect()))), Err(GameError::WrongRole(2)));
+        assert_eq!(state.advance_game(action(Action::Eat(vec![(1, 3)].into_iter().collect()))), Err(GameError::CannotAct(1)));
+        state.players[3].character = None;
+        assert_eq!(state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))), Err(GameError::DeadPlayer(3)));
+    }
+
+    #[test]
+    fn eat_requires_every_wolf() {
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single), (Role::Seer, Identity::Doyle)]);
+        assert_rejected(&mut state, action(Action::Eat(HashMap::default())), GameError::MissingVote(0));
+        assert_rejected(&mut state, action(Action::Eat(vec![(0, 3)].into_iter().collect())), GameError::MissingVote(1));
+        // a wolf who can't act doesn't have to vote
+        state.players[1].character.as_mut().unwrap().ready = false;
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        assert!(state.players[3].character.is_none());
+    }
+
+    #[test]
+    fn revotes_limited() {
+        let tie = || action(Action::Eat(vec![(0, 3), (1, 4)].into_iter().collect()));
+        let mut state = eat(Tie::Revote, &[(0, 3), (1, 4)]);
+        for _ in 1..MAX_REVOTES {
+            state.advance_game(tie()).unwrap();
+            assert_eq!(state.phase, Some(Role::Wolf));
+        }
+        state.advance_game(tie()).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert!(state.players.iter().all(|player| player.character.is_some()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Romeo), (Role::Angel, Identity::Juliet), (Role::Seer, Identity::Solo(SoloIdentity::Sherlock)), (Role::Witch, Identity::Doyle)]);
+        state.points.insert(3, 2);
+        state.advance_game(action(Action::Aim(0, 2))).unwrap();
+        let json = serde_json::to_string(&state).unwrap();
+        let mut roundtripped = serde_json::from_str::<State<usize>>(&json).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&serde_json::to_string(&roundtripped).unwrap()).unwrap(), serde_json::from_str::<serde_json::Value>(&json).unwrap());
+        assert_eq!(roundtripped.rng.gen::<u64>(), state.rng.gen::<u64>());
+        let input = action(Action::Eat(vec![(0, 2), (1, 3)].into_iter().collect()));
+        let json = serde_json::to_string(&input).unwrap();
+        assert!(matches!(serde_json::from_str::<Input<usize>>(&json).unwrap(), Input::Ingame(IngameInput::Action(Action::Eat(votes))) if votes[&0] == 2 && votes[&1] == 3));
+    }
+
+    #[test]
+    fn replay() {
+        let mut state = State::with_seed(Rules::default(), 0);
+        for player in 0..7 { state.advance_game(Input::Meta(MetaInput::Join(player, None))).unwrap(); }
+        state.advance_game(Input::Meta(MetaInput::Go)).unwrap();
+        let hunter = state.living_players_with_role(Role::Hunter).next().unwrap();
+        state.advance_game(action(Action::Aim(hunter, (hunter + 1) % 7))).unwrap();
+        assert!(state.advance_game(action(Action::Shoot(hunter))).is_err());
+        let replayed = state.log().replay().unwrap();
+        assert_eq!(format!("{:?}", replayed.players), format!("{:?}", state.players));
+        assert_eq!(replayed.phase, state.phase);
+        assert_eq!(replayed.targets, state.targets);
+        assert_eq!(replayed.history.len(), 9);
+    }
+
+    #[test]
+    fn same_seed_same_deal() {
+        let deal = |seed| {
+            let mut state = State::with_seed(Rules::default(), seed);
+            for player in 0..9 { state.advance_game(Input::Meta(MetaInput::Join(player, None))).unwrap(); }
+            state.advance_game(Input::Meta(MetaInput::Go)).unwrap();
+            state.players.iter().map(|player| {
+                let c = player.character.as_ref().expect("player wasn't dealt a character");
+                (player.id, c.role, c.identity)
+            }).collect_vec()
+        };
+        assert_eq!(deal(42), deal(42));
+        assert_ne!(deal(42), deal(43));
+    }
+
+    #[test]
+    fn legal_inputs() {
+        let mut state = State::with_seed(Rules::default(), 0);
+        assert_eq!(state.legal_inputs_for(&0).len(), 2);
+        for player in 0..8 { state.advance_game(Input::Meta(MetaInput::Join(player, None))).unwrap(); }
+        assert_eq!(state.legal_inputs_for(&8).len(), 10);
+        assert_eq!(state.legal_inputs_for(&0).len(), 2);
+        state.advance_game(Input::Meta(MetaInput::Go)).unwrap();
+        let mut rng = Pcg64::seed_from_u64(0);
+        for _ in 0..100 {
+            if state.phase.is_none() { break }
+            let inputs = state.legal_inputs();
+            assert!(!inputs.is_empty());
+            for input in &inputs {
+                state.log().replay().unwrap().advance_game(input.clone()).unwrap();
+            }
+            // each player's inputs are legal, and each legal action (except for combined wolf votes) can be given by someone
+            let per_player = state.players.iter().map(|player| state.legal_inputs_for(&player.id)).collect_vec();
+            if state.phase != Some(Role::Wolf) {
+                for input in per_player.iter().flatten() {
+                    state.log().replay().unwrap().advance_game(input.clone()).unwrap();
+                }
+                assert_eq!(per_player.iter().map(Vec::len).sum::<usize>(), inputs.len());
+            }
+            state.advance_game(inputs.choose(&mut rng).unwrap().clone()).unwrap();
+        }
+    }
+
+    #[test]
+    fn views() {
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Witch, Identity::Single), (Role::Seer, Identity::Doyle)]);
+        state.players[3].character.as_mut().unwrap().bitten_by = Some(2);
+        let view = state.view_for(&0);
+        assert_eq!(view.character.unwrap().identity, Identity::Romeo);
+        assert_eq!(view.wolves, vec![0, 1]);
+        let view = state.view_for(&2);
+        assert_eq!(view.bitten, vec![(3, Role::Seer)]);
+        assert!(view.wolves.is_empty());
+        let view = state.spectator_view();
+        assert!(view.character.is_none());
+        assert!(view.players.iter().all(|&(_, alive)| alive));
+        state.advance_game(action(Action::Eat(vec![(0, 3), (1, 3)].into_iter().collect()))).unwrap();
+        assert_eq!(state.view_for(&2).dying, Some(3));
+        assert_eq!(state.view_for(&0).dying, None);
+    }
+
+    #[test]
+    fn bitten_angel_view() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Angel, Identity::Single), (Role::Wolf, Identity::Doyle)]);
+        state.players[2].character.as_mut().unwrap().bitten_by = Some(0);
+        state.advance_game(action(Action::Inspect(3))).unwrap();
+        assert!(matches!(state.next_input(), InputRequest::Angel(Role::Seer, Action::Inspect(3), ref players) if *players == [0]));
+        // the vampire decides for the angel, so they need to know what to veto
+        let view = state.view_for(&0);
+        assert!(matches!(view.pending_action, Some(Action::Inspect(3))));
+        assert_eq!(view.pending_actors, vec![1]);
+        assert!(state.view_for(&2).pending_action.is_none());
+    }
+
+    #[test]
+    fn notifications() {
+        let mut state = game(Rules::default(), Role::Vampire, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Wolf, Identity::Single)]);
+        assert_eq!(state.advance_game(action(Action::Bite(2))).unwrap(), vec![(Recipient::Player(2), Event::Bitten)]);
+        let notifications = state.advance_game(action(Action::Inspect(2))).unwrap();
+        assert_eq!(notifications[0], (Recipient::Player(1), Event::Insight(Insight::Identity(2, Identity::Single))));
+        let notifications = state.advance_game(win_attempt(0, Identity::Romeo, vec![1])).unwrap();
+        assert_eq!(notifications, vec![
+            (Recipient::Everyone, Event::Victory(vec![0, 1])),
+            (Recipient::Player(0), Event::PointsWon(2)),
+            (Recipient::Player(1), Event::PointsWon(2)),
+        ]);
+    }
+
+    #[test]
+    fn deal() {
+        let mut state = State::with_seed(Rules::default(), 0);
+        for player in 0..3 { state.advance_game(Input::Meta(MetaInput::Join(player, Some(player)))).unwrap(); }
+        let deal = |characters: &[(Seat, Role, Identity)]| Input::Meta(MetaInput::Deal(characters.to_vec()));
+        assert_eq!(state.advance_game(deal(&[(0, Role::Wolf, Identity::Romeo), (1, Role::Seer, Identity::Juliet)])), Err(GameError::NotDealt(2)));
+        assert_eq!(state.advance_game(deal(&[(0, Role::Wolf, Identity::Romeo), (0, Role::Seer, Identity::Juliet)])), Err(GameError::AlreadyDealt(0)));
+        assert_eq!(state.advance_game(deal(&[(0, Role::Seer, Identity::Romeo), (1, Role::Seer, Identity::Juliet)])), Err(GameError::UnavailableRole(Role::Seer)));
+        assert_eq!(state.advance_game(deal(&[(0, Role::Wolf, Identity::Romeo), (3, Role::Seer, Identity::Juliet)])), Err(GameError::NoSuchSeat(3)));
+        let notifications = state.advance_game(deal(&[(0, Role::Wolf, Identity::Romeo), (1, Role::Seer, Identity::Juliet), (2, Role::Wolf, Identity::Single)])).unwrap();
+        assert_eq!(notifications[1], (Recipient::Player(1), Event::Character(Role::Seer, Identity::Juliet)));
+        // nobody was dealt the hunter, vampire, or jester, so the segment starts in the seer phase
+        assert_eq!(state.phase, Some(Role::Seer));
+    }
+
+    #[test]
+    fn skipped_phases() {
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Mayor, Identity::Juliet), (Role::Seer, Identity::Single), (Role::Wolf, Identity::Doyle)]);
+        state.players[1].character.as_mut().unwrap().ready = false;
+        state.players[2].character.as_mut().unwrap().ready = false;
+        let start = state.position().unwrap();
+        assert_eq!(start.skipped_since(None), vec![Role::Hunter, Role::Vampire, Role::Jester, Role::Seer]);
+        assert!(start.skipped_since(Some(start)).is_empty());
+        // nobody else can act in this cycle, and the seer has been eaten by the next one
+        state.advance_game(action(Action::Eat(vec![(0, 2), (3, 2)].into_iter().collect()))).unwrap();
+        let position = state.position().unwrap();
+        assert_eq!(position, Position { cycle: 1, phase: Role::Wolf });
+        assert_eq!(position.skipped_since(Some(start)), vec![Role::Witch, Role::Mayor, Role::Hunter, Role::Vampire, Role::Jester, Role::Seer]);
+    }
+
+    #[test]
+    fn addressed_requests() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Angel, Identity::Single), (Role::Wolf, Identity::Doyle)]);
+        state.players[1].character.as_mut().unwrap().bitten_by = Some(0);
+        match state.next_input() {
+            InputRequest::Action(Role::Seer, players) => assert_eq!(players, vec![0]),
+            ir => panic!("unexpected input request: {:?}", ir),
+        }
+        state.advance_game(action(Action::Inspect(3))).unwrap();
+        match state.next_input() {
+            InputRequest::Angel(Role::Seer, Action::Inspect(3), players) => assert_eq!(players, vec![2]),
+            ir => panic!("unexpected input request: {:?}", ir),
+        }
+    }
+
+    #[test]
+    fn angel_without_token() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Angel, Identity::Single), (Role::Wolf, Identity::Doyle)]);
+        state.players[2].character.as_mut().unwrap().bitten_by = Some(0);
+        state.players[0].character.as_mut().unwrap().ready = false;
+        // the vampire acting for the angel has used their token, so nobody can veto the inspection
+        state.advance_game(action(Action::Inspect(3))).unwrap();
+        assert!(state.pending_action.is_none());
+        assert_eq!(state.phase, Some(Role::Wolf));
+    }
+
+    /// Asserts that the input is rejected with the given error and doesn't change the state.
+    fn assert_rejected(state: &mut State<usize>, input: Input<usize>, error: GameError) {
+        let before = format!("{:?}", state);
+        assert_eq!(state.advance_game(input), Err(error));
+        assert_eq!(format!("{:?}", state), before);
+    }
+
+    /// A game between segments where the given number of players have joined in seat order.
+    fn lobby(players: usize) -> State<usize> {
+        let mut state = State::with_seed(Rules::default(), 0);
+        for player in 0..players { state.advance_game(Input::Meta(MetaInput::Join(player, Some(player)))).unwrap(); }
+        state
+    }
+
+    #[test]
+    fn error_meta_input_ingame() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Mayor, Identity::Juliet)]);
+        assert_rejected(&mut state, Input::Meta(MetaInput::Join(2, None)), GameError::MetaInputIngame);
+    }
+
+    #[test]
+    fn error_between_segments() {
+        let mut state = lobby(3);
+        assert_rejected(&mut state, action(Action::VoteResult(None)), GameError::BetweenSegments);
+    }
+
+    #[test]
+    fn error_already_joined() {
+        let mut state = lobby(3);
+        assert_rejected(&mut state, Input::Meta(MetaInput::Join(1, None)), GameError::AlreadyJoined);
+    }
+
+    #[test]
+    fn error_just_quit() {
+        let mut state = lobby(3);
+        state.advance_game(Input::Meta(MetaInput::Quit(1))).unwrap();
+        assert_rejected(&mut state, Input::Meta(MetaInput::Join(1, None)), GameError::JustQuit);
+    }
+
+    #[test]
+    fn error_not_in_game() {
+        let mut state = lobby(3);
+        assert_rejected(&mut state, Input::Meta(MetaInput::Quit(3)), GameError::NotInGame);
+    }
+
+    #[test]
+    fn error_not_enough_characters() {
+        let mut state = lobby(Identity::into_enum_iter().count() + 1);
+        assert_rejected(&mut state, Input::Meta(MetaInput::Go), GameError::NotEnoughCharacters);
+    }
+
+    #[test]
+    fn error_no_such_seat() {
+        let mut state = lobby(3);
+        assert_rejected(&mut state, Input::Meta(MetaInput::Join(3, Some(4))), GameError::NoSuchSeat(4));
+        let mut state = game(Rules::default(), Role::Vampire, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        assert_rejected(&mut state, action(Action::Bite(2)), GameError::NoSuchSeat(2));
+    }
+
+    #[test]
+    fn error_dead_player() {
+        let mut state = game(Rules::default(), Role::Vampire, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.players[2].character = None;
+        assert_rejected(&mut state, action(Action::Bite(2)), GameError::DeadPlayer(2));
+    }
+
+    #[test]
+    fn error_wrong_phase() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        assert_rejected(&mut state, action(Action::Bite(1)), GameError::WrongPhase(Role::Seer));
+    }
+
+    #[test]
+    fn error_cannot_act() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.players[1].character.as_mut().unwrap().ready = false;
+        assert_rejected(&mut state, action(Action::Inspect(0)), GameError::CannotAct(1));
+    }
+
+    #[test]
+    fn error_ambiguous_actor() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        assert_rejected(&mut state, action(Action::Inspect(2)), GameError::AmbiguousActor);
+    }
+
+    #[test]
+    fn error_no_target() {
+        let mut state = game(Rules::default(), Role::Hunter, &[(Role::Hunter, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        assert_rejected(&mut state, action(Action::Shoot(0)), GameError::NoTarget);
+    }
+
+    #[test]
+    fn error_action_pending() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Angel, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.advance_game(action(Action::Inspect(2))).unwrap();
+        assert_rejected(&mut state, action(Action::Inspect(1)), GameError::ActionPending);
+    }
+
+    #[test]
+    fn error_no_pending_action() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Angel, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        assert_rejected(&mut state, Input::Ingame(IngameInput::AngelPass), GameError::NoPendingAction);
+        assert_rejected(&mut state, Input::Ingame(IngameInput::AngelVeto(0)), GameError::NoPendingAction);
+    }
+
+    #[test]
+    fn error_not_an_actor() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Angel, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.advance_game(action(Action::Inspect(2))).unwrap();
+        assert_rejected(&mut state, Input::Ingame(IngameInput::AngelVeto(2)), GameError::NotAnActor(2));
+    }
+
+    #[test]
+    fn error_shielded() {
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Angel, Identity::Single), (Role::Mayor, Identity::Doyle)]);
+        state.shielded = Some(1);
+        state.advance_game(action(Action::Eat(vec![(0, 3), (1, 3)].into_iter().collect()))).unwrap();
+        assert_rejected(&mut state, Input::Ingame(IngameInput::AngelVeto(1)), GameError::Shielded(1));
+    }
+
+    /// A game in the wolf phase with a single wolf and the witch.
+    fn witch_game() -> State<usize> {
+        game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Witch, Identity::Juliet), (Role::Mayor, Identity::Single), (Role::Seer, Identity::Doyle)])
+    }
+
+    #[test]
+    fn witch_heals() {
+        let mut state = witch_game();
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        assert_eq!(state.phase, Some(Role::Witch));
+        assert_eq!(state.dying, Some(3));
+        assert!(state.players[3].character.is_some());
+        state.advance_game(action(Action::Potions { heal: true, poison: None })).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert_eq!(state.dying, None);
+        assert!(state.players[3].character.is_some());
+        assert!(matches!(character(&state, 1).props, Props::Witch { health_potion: false, poison_potion: true }));
+    }
+
+    #[test]
+    fn witch_poisons() {
+        let mut state = witch_game();
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        state.advance_game(action(Action::Potions { heal: false, poison: Some(2) })).unwrap();
+        assert!(state.players[2].character.is_none());
+        assert!(state.players[3].character.is_none());
+        assert!(matches!(character(&state, 1).props, Props::Witch { health_potion: true, poison_potion: false }));
+        // the mayor and seer are dead, so the next phase with anyone to act is the wolves'
+        assert_eq!(state.phase, Some(Role::Wolf));
+    }
+
+    #[test]
+    fn dying_player_killed_after_witch_phase() {
+        let mut state = witch_game();
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        state.advance_game(action(Action::Potions { heal: false, poison: None })).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert_eq!(state.dying, None);
+        assert!(state.players[3].character.is_none());
+        assert!(matches!(character(&state, 1).props, Props::Witch { health_potion: true, poison_potion: true }));
+    }
+
+    #[test]
+    fn error_no_potion() {
+        let mut state = witch_game();
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        state.players[1].character.as_mut().unwrap().props = Props::Witch { health_potion: false, poison_potion: true };
+        assert_rejected(&mut state, action(Action::Potions { heal: true, poison: None }), GameError::NoPotion);
+        state.players[1].character.as_mut().unwrap().props = Props::Witch { health_potion: true, poison_potion: false };
+        assert_rejected(&mut state, action(Action::Potions { heal: false, poison: Some(2) }), GameError::NoPotion);
+    }
+
+    #[test]
+    fn error_nobody_dying() {
+        let mut state = game(Rules::default(), Role::Witch, &[(Role::Wolf, Identity::Romeo), (Role::Witch, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        assert_rejected(&mut state, action(Action::Potions { heal: true, poison: None }), GameError::NobodyDying);
+    }
+
+    #[test]
+    fn witch_phase_skipped_without_usable_potions() {
+        let mut state = witch_game();
+        state.players[1].character.as_mut().unwrap().props = Props::Witch { health_potion: false, poison_potion: false };
+        state.advance_game(action(Action::Eat(vec![(0, 3)].into_iter().collect()))).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        assert!(state.players[3].character.is_none());
+        // the health potion is only of use if someone is dying
+        let mut state = game(Rules::default(), Role::Wolf, &[(Role::Wolf, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Witch, Identity::Single), (Role::Mayor, Identity::Doyle)]);
+        state.players[2].character.as_mut().unwrap().props = Props::Witch { health_potion: true, poison_potion: false };
+        state.advance_game(action(Action::Eat(vec![(0, 2), (1, 3)].into_iter().collect()))).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+    }
+
+    #[test]
+    fn tokens_refreshed_each_cycle() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Mayor, Identity::Juliet)]);
+        state.advance_game(action(Action::Inspect(1))).unwrap();
+        assert_eq!(state.phase, Some(Role::Mayor));
+        state.advance_game(action(Action::VoteResult(None))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+        state.advance_game(action(Action::Inspect(1))).unwrap();
+        assert_eq!(state.insights(&0).len(), 2);
+    }
+
+    #[test]
+    fn win_ends_segment() {
+        let mut state = game(Rules::default(), Role::Witch, &[(Role::Witch, Identity::Solo(SoloIdentity::Macbeth)), (Role::Mayor, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        state.advance_game(action(Action::Potions { heal: false, poison: Some(1) })).unwrap();
+        assert_eq!(state.phase, None);
+        assert_eq!(state.points.get(&0), Some(&2));
+    }
+
+    #[test]
+    fn tokens_refreshed_for_next_segment() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Juliet), (Role::Witch, Identity::Solo(SoloIdentity::Macbeth)), (Role::Mayor, Identity::Romeo)]);
+        state.advance_game(action(Action::Inspect(1))).unwrap();
+        // Macbeth wins in the middle of the cycle, after the seer has acted
+        state.advance_game(action(Action::Potions { heal: false, poison: Some(2) })).unwrap();
+        assert_eq!(state.phase, None);
+        state.advance_game(Input::Meta(MetaInput::Deal(vec![(1, Role::Wolf, Identity::Single), (2, Role::Mayor, Identity::Doyle)]))).unwrap();
+        assert_eq!(state.phase, Some(Role::Seer));
+    }
+
+    #[test]
+    fn segment_ends_when_nobody_can_act() {
+        let mut state = game(Rules::default(), Role::Witch, &[(Role::Witch, Identity::Romeo), (Role::Seer, Identity::Juliet)]);
+        state.players[0].character.as_mut().unwrap().props = Props::Witch { health_potion: false, poison_potion: true };
+        // after the seer dies, the witch is left without potions, so there's no phase anyone could act in
+        state.advance_game(action(Action::Potions { heal: false, poison: Some(1) })).unwrap();
+        assert_eq!(state.phase, None);
+        assert!(state.points.is_empty());
+    }
+
+    #[test]
+    fn inspect_identity() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.advance_game(action(Action::Inspect(1))).unwrap();
+        assert_eq!(state.insights(&0), [Insight::Identity(1, Identity::Juliet)]);
+        assert!(state.insights(&1).is_empty());
+    }
+
+    #[test]
+    fn inspect_role() {
+        let mut state = game(Rules { inspection: Inspection::Role, ..Rules::default() }, Role::Seer, &[(Role::Seer, Identity::Romeo), (Role::Wolf, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.advance_game(action(Action::Inspect(1))).unwrap();
+        assert_eq!(state.insights(&0), [Insight::Role(1, Role::Wolf)]);
+    }
+
+    #[test]
+    fn bitten_seer_inspects_for_vampire() {
+        let mut state = game(Rules::default(), Role::Seer, &[(Role::Vampire, Identity::Romeo), (Role::Seer, Identity::Juliet), (Role::Mayor, Identity::Single)]);
+        state.players[1].character.as_mut().unwrap().bitten_by = Some(0);
+        let notifications = state.advance_game(action(Action::Inspect(2))).unwrap();
+        assert_eq!(notifications[0], (Recipient::Player(0), Event::Insight(Insight::Identity(2, Identity::Single))));
+        assert_eq!(state.insights(&0), [Insight::Identity(2, Identity::Single)]);
+        assert!(state.insights(&1).is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+//! A full-screen terminal interface, for a moderator running the game without typing player names.
+
+use {
+    std::{
+        collections::HashMap,
+        f64::consts::PI,
+        io::{
+            self,
+            Stdout,
+            stdout,
+        },
+    },
+    crossterm::{
+        event::{
+            self,
+            Event as TermEvent,
+            KeyCode,
+            KeyEvent,
+            KeyEventKind,
+            KeyModifiers,
+        },
+        execute,
+        terminal::{
+            EnterAlternateScreen,
+            LeaveAlternateScreen,
+            disable_raw_mode,
+            enable_raw_mode,
+        },
+    },
+    enum_iterator::IntoEnumIterator as _,
+    itertools::Itertools as _,
+    tui::{
+        Frame,
+        Terminal,
+        backend::{
+            Backend,
+            CrosstermBackend,
+        },
+        layout::{
+            Constraint,
+            Direction,
+            Layout,
+        },
+        style::{
+            Color,
+            Modifier,
+            Style,
+        },
+        text::{
+            Span,
+            Spans,
+        },
+        widgets::{
+            Block,
+            Borders,
+            List,
+            ListItem,
+            ListState,
+            Paragraph,
+            Row,
+            Table,
+            Wrap,
+            canvas::Canvas,
+        },
+    },
+    victory::{
+        frontend::Frontend,
+        identities::Identity,
+        roles::Role,
+        state::{
+            Action,
+            Event,
+            GameError,
+            IngameInput,
+            Input,
+            InputRequest,
+            MetaInput,
+            Recipient,
+            State,
+        },
+    },
+    crate::{
+        describe_action,
+        describe_event,
+    },
+};
+
+/// What the moderator is currently being asked for.
+enum Prompt<'a> {
+    Menu {
+        title: &'a str,
+        options: &'a [String],
+        selected: usize,
+    },
+    Seat {
+        title: &'a str,
+        cursor: usize,
+        optional: bool,
+    },
+    Text {
+        title: &'a str,
+        text: &'a str,
+    },
+}
+
+/// Ways to leave a prompt other than answering it.
+enum Interrupt {
+    /// Start over with the current input request.
+    Back,
+    /// End the game.
+    Quit,
+    Io(io::Error),
+}
+
+impl From<io::Error> for Interrupt {
+    fn from(e: io::Error) -> Interrupt {
+        Interrupt::Io(e)
+    }
+}
+
+pub(crate) struct Tui {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    /// notifications and rejected inputs, oldest first
+    log: Vec<String>,
+    /// the players whose input is awaited, highlighted at the table
+    awaited: Vec<String>,
+    /// the phase indicator
+    status: String,
+}
+
+impl Tui {
+    pub(crate) fn new() -> io::Result<Tui> {
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen)?;
+        Ok(Tui {
+            terminal: Terminal::new(CrosstermBackend::new(stdout()))?,
+            log: Vec::default(),
+            awaited: Vec::default(),
+            status: String::default(),
+        })
+    }
+
+    fn draw(&mut self, state: &State<String>, prompt: Prompt<'_>) -> io::Result<()> {
+        let Tui { terminal, log, awaited, status } = self;
+        terminal.draw(|f| render(f, state, log, awaited, status, &prompt))?;
+        Ok(())
+    }
+
+    /// Returns the next key press, or `Interrupt::Quit` if it was Ctrl+C.
+    fn key(&mut self) -> Result<KeyEvent, Interrupt> {
+        loop {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release { continue }
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) { return Err(Interrupt::Quit) }
+                return Ok(key)
+            }
+        }
+    }
+
+    fn menu(&mut self, state: &State<String>, title: &str, options: &[&str]) -> Result<usize, Interrupt> {
+        let options = options.iter().map(|&option| option.to_owned()).collect_vec();
+        let mut selected = 0;
+        loop {
+            self.draw(state, Prompt::Menu { title, options: &options, selected })?;
+            match self.key()?.code {
+                KeyCode::Up => selected = (selected + options.len() - 1) % options.len(),
+                KeyCode::Down => selected = (selected + 1) % options.len(),
+                KeyCode::Enter => return Ok(selected),
+                KeyCode::Esc => return Err(Interrupt::Back),
+                KeyCode::Char('q') => return Err(Interrupt::Quit),
+                _ => {}
+            }
+        }
+    }
+
+    fn yes_no(&mut self, state: &State<String>, title: &str) -> Result<bool, Interrupt> {
+        Ok(self.menu(state, title, &["no", "yes"])? == 1)
+    }
+
+    /// Lets the moderator pick a seat at the table. If `optional` is set, `n` picks nobody.
+    fn seat(&mut self, state: &State<String>, title: &str, optional: bool) -> Result<Option<usize>, Interrupt> {
+        let num_players = state.players().len();
+        if num_players == 0 { return if optional { Ok(None) } else { Err(Interrupt::Back) } }
+        let mut cursor = 0;
+        loop {
+            self.draw(state, Prompt::Seat { title, cursor, optional })?;
+            match self.key()?.code {
+                KeyCode::Left | KeyCode::Up => cursor = (cursor + num_players - 1) % num_players,
+                KeyCode::Right | KeyCode::Down => cursor = (cursor + 1) % num_players,
+                KeyCode::Enter => return Ok(Some(cursor)),
+                KeyCode::Char('n') if optional => return Ok(None),
+                KeyCode::Esc => return Err(Interrupt::Back),
+                KeyCode::Char('q') => return Err(Interrupt::Quit),
+                _ => {}
+            }
+        }
+    }
+
+    fn required_seat(&mut self, state: &State<String>, title: &str) -> Result<usize, Interrupt> {
+        Ok(self.seat(state, title, false)?.expect("got no seat from non-optional prompt"))
+    }
+
+    fn text(&mut self, state: &State<String>, title: &str) -> Result<String, Interrupt> {
+        let mut text = String::default();
+        loop {
+            self.draw(state, Prompt::Text { title, text: &text })?;
+            match self.key()?.code {
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Backspace => { text.pop(); }
+                KeyCode::Enter => return Ok(text),
+                KeyCode::Esc => return Err(Interrupt::Back),
+                _ => {}
+            }
+        }
+    }
+
+    fn input(&mut self, state: &State<String>, ir: &InputRequest<String>) -> Result<Input<String>, Interrupt> {
+        Ok(match *ir {
+            InputRequest::Meta => Input::Meta(match self.menu(state, "between segments", &["add player", "remove player", "start"])? {
+                0 => MetaInput::Join(self.text(state, "name of the new player")?, None),
+                1 => MetaInput::Quit(state.players()[self.required_seat(state, "player to remove")?].id.clone()),
+                _ => MetaInput::Go,
+            }),
+            InputRequest::Action(phase, _) => Input::Ingame(self.action(state, phase)?),
+            InputRequest::Angel(_, _, _) => Input::Ingame(match self.menu(state, "angel", &["pass", "veto", "win attempt"])? {
+                0 => IngameInput::AngelPass,
+                1 => IngameInput::AngelVeto(self.required_seat(state, "vetoed player")?),
+                _ => self.win_attempt(state)?,
+            }),
+        })
+    }
+
+    fn action(&mut self, state: &State<String>, phase: Role) -> Result<IngameInput, Interrupt> {
+        let options: &[&str] = match phase {
+            Role::Hunter => &["aim", "shoot", "win attempt"],
+            Role::Vampire => &["bite", "win attempt"],
+            Role::Jester => &["swap", "win attempt"],
+            Role::Seer => &["inspect", "win attempt"],
+            Role::Wolf => &["eat", "win attempt"],
+            Role::Witch => &["potions", "win attempt"],
+            Role::Mayor => &["vote", "win attempt"],
+            Role::Angel => &["win attempt"],
+        };
+        Ok(IngameInput::Action(match options[self.menu(state, "action", options)?] {
+            "aim" => Action::Aim(self.required_seat(state, "hunter")?, self.required_seat(state, "target")?),
+            "shoot" => Action::Shoot(self.required_seat(state, "hunter")?),
+            "bite" => Action::Bite(self.required_seat(state, "bitten player")?),
+            "swap" => Action::Swap(self.required_seat(state, "first player")?, self.required_seat(state, "second player")?),
+            "inspect" => Action::Inspect(self.required_seat(state, "inspected player")?),
+            "eat" => {
+                let mut votes = HashMap::default();
+                while let Some(wolf) = self.seat(state, "wolf (n when all wolves have voted)", true)? {
+                    votes.insert(wolf, self.required_seat(state, &format!("player eaten by {}", state.players()[wolf].id))?);
+                }
+                Action::Eat(votes)
+            }
+            "potions" => Action::Potions {
+                heal: self.yes_no(state, "use the health potion?")?,
+                poison: self.seat(state, "poisoned player (n to keep the poison potion)", true)?,
+            },
+            "vote" => Action::VoteResult(self.seat(state, "executed player (n for nobody)", true)?),
+            _ => return self.win_attempt(state),
+        }))
+    }
+
+    fn win_attempt(&mut self, state: &State<String>) -> Result<IngameInput, Interrupt> {
+        let claimant = self.required_seat(state, "claimant")?;
+        let identities = Identity::into_enum_iter().collect_vec();
+        let names = identities.iter().map(Identity::to_string).collect_vec();
+        let identity = identities[self.menu(state, "claimed identity", &names.iter().map(String::as_str).collect_vec())?];
+        let mut partners = Vec::default();
+        while let Some(partner) = self.seat(state, "partner (n when done)", true)? {
+            partners.push(partner);
+        }
+        Ok(IngameInput::WinAttempt { claimant, identity, partners })
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen);
+    }
+}
+
+impl Frontend<String> for Tui {
+    type Error = io::Error;
+
+    fn give_input(&mut self, state: &State<String>, ir: InputRequest<String>) -> io::Result<Option<Input<String>>> {
+        let (status, awaited) = match ir {
+            InputRequest::Meta => ("between segments".to_owned(), Vec::default()),
+            InputRequest::Action(phase, ref awaited) => (format!("{} phase", phase), awaited.clone()),
+            InputRequest::Angel(phase, ref action, ref awaited) => (format!("{} phase, pending: {}", phase, describe_action(state, action)), awaited.clone()),
+        };
+        self.status = status;
+        self.awaited = awaited;
+        loop {
+            match self.input(state, &ir) {
+                Ok(input) => return Ok(Some(input)),
+                Err(Interrupt::Back) => {}
+                Err(Interrupt::Quit) => return Ok(None),
+                Err(Interrupt::Io(e)) => return Err(e),
+            }
+        }
+    }
+
+    fn notify(&mut self, recipient: Recipient<String>, event: Event<String>) -> io::Result<()> {
+        let text = describe_event(event);
+        self.log.push(match recipient {
+            Recipient::Everyone => text,
+            Recipient::Player(name) => format!("[for {} only] {}", name, text),
+        });
+        Ok(())
+    }
+
+    fn reject(&mut self, error: GameError) -> io::Result<()> {
+        self.log.push(format!("error: {}", error));
+        Ok(())
+    }
+}
+
+fn render<B: Backend>(f: &mut Frame<'_, B>, state: &State<String>, log: &[String], awaited: &[String], status: &str, prompt: &Prompt<'_>) {
+    let view = state.spectator_view();
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(f.size());
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Length(view.players.len() as u16 + 3), Constraint::Min(6), Constraint::Length(10)])
+        .split(columns[1]);
+    // seat circle, starting at the top and going clockwise
+    let cursor = if let Prompt::Seat { cursor, .. } = *prompt { Some(cursor) } else { None };
+    let labels = view.players.iter().enumerate().map(|(seat, (name, alive))| {
+        let angle = PI / 2.0 - 2.0 * PI * seat as f64 / view.players.len() as f64;
+        let mut style = if *alive { Style::default().fg(Color::Green) } else { Style::default().fg(Color::DarkGray) };
+        if awaited.contains(name) { style = style.fg(Color::Yellow).add_modifier(Modifier::BOLD) }
+        if cursor == Some(seat) { style = style.add_modifier(Modifier::REVERSED) }
+        let label = if *alive { name.clone() } else { format!("{} ✝", name) };
+        (angle.cos(), angle.sin(), Span::styled(label, style))
+    }).collect_vec();
+    f.render_widget(Canvas::default()
+        .block(Block::default().borders(Borders::ALL).title("table"))
+        .x_bounds([-1.5, 1.5])
+        .y_bounds([-1.2, 1.2])
+        .paint(move |ctx| for (x, y, label) in &labels { ctx.print(*x, *y, label.clone()) }),
+        columns[0],
+    );
+    let status = if awaited.is_empty() { status.to_owned() } else { format!("{}, waiting for {}", status, awaited.join(", ")) };
+    f.render_widget(Paragraph::new(status).block(Block::default().borders(Borders::ALL).title("phase")), rows[0]);
+    let points = view.players.iter()
+        .map(|(name, _)| Row::new(vec![name.clone(), view.points.get(name).copied().unwrap_or_default().to_string()]))
+        .collect_vec();
+    let widths = [Constraint::Percentage(70), Constraint::Percentage(30)];
+    f.render_widget(Table::new(points)
+        .header(Row::new(vec!["player", "points"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title("points"))
+        .widths(&widths),
+        rows[1],
+    );
+    match *prompt {
+        Prompt::Menu { title, options, selected } => {
+            let mut list_state = ListState::default();
+            list_state.select(Some(selected));
+            f.render_stateful_widget(List::new(options.iter().map(|option| ListItem::new(option.as_str())).collect_vec())
+                .block(Block::default().borders(Borders::ALL).title(format!("{} (↑/↓, enter, esc to go back)", title)))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+                .highlight_symbol("> "),
+                rows[2],
+                &mut list_state,
+            );
+        }
+        Prompt::Seat { title, optional, .. } => {
+            let help = if optional { "←/→ to choose, enter to confirm, n for nobody, esc to go back" } else { "←/→ to choose, enter to confirm, esc to go back" };
+            f.render_widget(Paragraph::new(help).wrap(Wrap { trim: true }).block(Block::default().borders(Borders::ALL).title(title)), rows[2]);
+        }
+        Prompt::Text { title, text } => {
+            f.render_widget(Paragraph::new(format!("{}_", text)).block(Block::default().borders(Borders::ALL).title(format!("{} (enter to confirm, esc to go back)", title))), rows[2]);
+        }
+    }
+    let log_lines = log.iter().rev().take(rows[3].height.saturating_sub(2).into()).rev().map(|line| Spans::from(line.as_str())).collect_vec();
+    f.render_widget(Paragraph::new(log_lines).block(Block::default().borders(Borders::ALL).title("log")), rows[3]);
+}
@@ -0,0 +1,127 @@
+use {
+    std::collections::HashMap,
+    crate::{
+        identities::Identity,
+        player::Props,
+        roles::Role,
+        state::{
+            Action,
+            Insight,
+            PlayerId,
+            Seat,
+            State,
+        },
+    },
+};
+
+/// What a player or spectator is allowed to know about the game.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct View<P: PlayerId> {
+    /// the seat of the player this view is for, `None` for spectators
+    pub seat: Option<Seat>,
+    /// the viewer's own character, `None` for spectators and dead players
+    pub character: Option<OwnCharacter>,
+    /// everyone at the table in seat order, with whether they're alive
+    pub players: Vec<(P, bool)>,
+    /// `None` means between segments
+    pub phase: Option<Role>,
+    pub points: HashMap<P, u32>,
+    /// what the viewer has learned privately during this segment
+    pub insights: Vec<Insight<P>>,
+    /// the players the viewer has bitten and acts for, with their roles
+    pub bitten: Vec<(Seat, Role)>,
+    /// the living wolves, if the viewer is one of them or acts for one
+    pub wolves: Vec<Seat>,
+    /// the player eaten by the wolves, if the viewer is or acts for the witch during her phase
+    pub dying: Option<Seat>,
+    /// the action waiting for approval, if the viewer is or acts for an angel
+    pub pending_action: Option<Action>,
+    /// the players taking the pending action, if the viewer is or acts for an angel
+    pub pending_actors: Vec<Seat>,
+    /// the player who can't be vetoed again right now, if the viewer is or acts for an angel
+    pub shielded: Option<Seat>,
+}
+
+/// A player's knowledge about their own character.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct OwnCharacter {
+    pub role: Role,
+    pub identity: Identity,
+    /// whether a vampire is acting for this player
+    pub bitten: bool,
+    /// for the witch, which potions are left, as `(health_potion, poison_potion)`
+    pub potions: Option<(bool, bool)>,
+    /// for a hunter, the player they're aiming at
+    pub target: Option<Seat>,
+}
+
+impl<P: PlayerId> View<P> {
+    /// Whether the viewer takes actions for the player in the given seat, i.e. it's their own seat and they haven't been bitten, or they bit that player.
+    pub fn acts_for(&self, seat: Seat) -> bool {
+        Some(seat) == self.seat && self.character.as_ref().map_or(false, |c| !c.bitten)
+        || self.bitten.iter().any(|&(bitten_seat, _)| bitten_seat == seat)
+    }
+}
+
+impl<P: PlayerId> State<P> {
+    /// Returns what the given player is allowed to know. Players who aren't in the game get a spectator view.
+    pub fn view_for(&self, player: &P) -> View<P> {
+        let seat = if let Some(seat) = self.players.iter().position(|iter_player| iter_player.id == *player) { seat } else { return self.spectator_view() };
+        let mut view = self.spectator_view();
+        view.seat = Some(seat);
+        view.insights = self.insights(player).to_vec();
+        if let Some(ref c) = self.players[seat].character {
+            view.character = Some(OwnCharacter {
+                role: c.role,
+                identity: c.identity,
+                bitten: c.bitten_by.is_some(),
+                potions: if let Props::Witch { health_potion, poison_potion } = c.props { Some((health_potion, poison_potion)) } else { None },
+                target: self.targets.get(&seat).copied(),
+            });
+            view.bitten = self.players.iter()
+                .enumerate()
+                .filter_map(|(bitten_seat, player)| player.character.as_ref().filter(|c| c.bitten_by == Some(seat)).map(|c| (bitten_seat, c.role)))
+                .collect();
+            // roles the viewer takes actions for, either their own or those of the players they bit
+            let bitten = &view.bitten;
+            let acts_as = |role| c.role == role && c.bitten_by.is_none() || bitten.iter().any(|&(_, bitten_role)| bitten_role == role);
+            let (wolf, witch, angel) = (acts_as(Role::Wolf), acts_as(Role::Witch), acts_as(Role::Angel));
+            if wolf {
+                view.wolves = self.players.iter()
+                    .enumerate()
+                    .filter(|(_, player)| player.character.as_ref().map_or(false, |c| c.role == Role::Wolf))
+                    .map(|(seat, _)| seat)
+                    .collect();
+            }
+            if witch && self.phase == Some(Role::Witch) { view.dying = self.dying }
+            if angel {
+                if let Some((ref actor_seats, ref action)) = self.pending_action {
+                    view.pending_action = Some(action.clone());
+                    view.pending_actors = actor_seats.clone();
+                }
+                view.shielded = self.shielded;
+            }
+        }
+        view
+    }
+
+    /// Returns what everyone is allowed to know, e.g. for spectators.
+    pub fn spectator_view(&self) -> View<P> {
+        View {
+            seat: None,
+            character: None,
+            players: self.players.iter().map(|player| (player.id.clone(), player.character.is_some())).collect(),
+            phase: self.phase,
+            points: self.points().clone(),
+            insights: Vec::default(),
+            bitten: Vec::default(),
+            wolves: Vec::default(),
+            dying: None,
+            pending_action: None,
+            pending_actors: Vec::default(),
+            shielded: None,
+        }
+    }
+}